@@ -0,0 +1,215 @@
+//! Integrity checks of individual entries
+//!
+//! Entries of archives in extended format may carry checksum of their original content as attribute named
+//! after the algorithm, such as `crc32=cbf43926`. It's computed by [`crate::compress::compress_into`] when
+//! enabled by [`crate::compress::EntryOptions::checksum`] and checked when entries are extracted.
+//!
+//! CRC32 is always available, SHA-256 needs cargo feature `sha256`.
+
+use std::io::{self, BufRead, Read};
+
+/// Algorithm of the entry checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Checksum {
+    /// CRC-32 (IEEE), cheap but detecting only accidental damage
+    Crc32,
+    /// SHA-256
+    Sha256,
+}
+
+impl Checksum {
+    /// Name used as key of the attribute
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Crc32 => "crc32",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    /// Parses name of the attribute
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Crc32, Self::Sha256]
+            .iter()
+            .copied()
+            .find(|checksum| checksum.name() == name)
+    }
+
+    /// Returns true if the checksum can be computed with enabled features
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Crc32 => true,
+            Self::Sha256 => cfg!(feature = "sha256"),
+        }
+    }
+
+    /// Starts computation of the checksum
+    pub fn hasher(self) -> io::Result<Hasher> {
+        Ok(Hasher(match self {
+            Self::Crc32 => HasherKind::Crc32(!0),
+            #[cfg(feature = "sha256")]
+            Self::Sha256 => HasherKind::Sha256(Box::default()),
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("support of {} checksums is not enabled", self.name()),
+                ))
+            }
+        }))
+    }
+}
+
+/// Finds the checksum stored in entry attributes, along with its expected value
+///
+/// Checksums that can't be computed with enabled features are returned too, see [`Checksum::is_supported`].
+pub fn expected(attributes: &[(String, String)]) -> Option<(Checksum, &str)> {
+    attributes.iter().find_map(|(key, value)| {
+        Checksum::from_name(key).map(|checksum| (checksum, value.as_str()))
+    })
+}
+
+/// Checksum computation in progress, see [`Checksum::hasher`]
+pub struct Hasher(HasherKind);
+
+enum HasherKind {
+    Crc32(u32),
+    #[cfg(feature = "sha256")]
+    Sha256(Box<sha2::Sha256>),
+}
+
+impl Hasher {
+    /// Adds data to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HasherKind::Crc32(crc) => *crc = crc32_update(*crc, data),
+            #[cfg(feature = "sha256")]
+            HasherKind::Sha256(hasher) => sha2::Digest::update(hasher.as_mut(), data),
+        }
+    }
+
+    /// Returns the checksum as lowercase hexadecimal string, as stored in the attribute
+    pub fn finish(self) -> String {
+        match self.0 {
+            HasherKind::Crc32(crc) => format!("{:08x}", !crc),
+            #[cfg(feature = "sha256")]
+            HasherKind::Sha256(hasher) => sha2::Digest::finalize(*hasher)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        }
+    }
+}
+
+/// Lookup table of CRC-32 with reversed polynomial 0xEDB88320
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Reader computing checksum of everything read or consumed through it
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+}
+
+impl<R> HashingReader<R> {
+    /// Wraps the reader, `hasher` is optional for convenience
+    pub(crate) fn new(inner: R, hasher: Option<Hasher>) -> Self {
+        Self { inner, hasher }
+    }
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.inner
+    }
+    /// Returns checksum of the data read so far
+    pub(crate) fn finish(self) -> Option<String> {
+        self.hasher.map(Hasher::finish)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        if let Some(hasher) = &mut self.hasher {
+            // Data were already buffered by the preceding fill_buf, so this can't fail
+            if let Ok(buf) = self.inner.fill_buf() {
+                hasher.update(&buf[..amt.min(buf.len())]);
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(checksum: Checksum, data: &[u8]) -> String {
+        let mut hasher = checksum.hasher().unwrap();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(digest(Checksum::Crc32, b""), "00000000");
+        assert_eq!(digest(Checksum::Crc32, b"123456789"), "cbf43926");
+
+        let mut reader = HashingReader::new(&b"123456789"[..], Checksum::Crc32.hasher().ok());
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        reader.consume(2);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.finish().unwrap(), "cbf43926");
+
+        let attributes = [
+            ("sha512".to_owned(), "00".to_owned()),
+            ("crc32".to_owned(), "cbf43926".to_owned()),
+        ];
+        assert_eq!(expected(&attributes), Some((Checksum::Crc32, "cbf43926")));
+        assert_eq!(expected(&attributes[..1]), None);
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            digest(Checksum::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Transparent compression of whole archive streams
+//!
+//! DCA itself stores payloads as they are, so archives may be wrapped in general purpose compression format.
+//! Reading functions such as [`crate::decompress::decompress_from`] detect the wrapping from its magic bytes,
+//! [`crate::compress::compress_files_with`] chooses it by extension of the archive name.
+//!
+//! Entries of archives in extended format can be compressed individually instead, see [`EntryCodec`].
+//!
+//! Support of individual formats is enabled by cargo features `gzip`, `deflate` and `zstd`. Without them, such
+//! streams are still recognized, but fail with [`io::ErrorKind::Unsupported`].
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+/// Compression format wrapping the whole archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Codec {
+    /// Plain archive
+    #[default]
+    None,
+    /// Gzip stream, with extension `.gz`
+    Gzip,
+    /// Zstandard stream, with extension `.zst`
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+
+impl Codec {
+    /// Recognizes format of the stream by its first bytes, without consuming them
+    pub fn detect(reader: &mut impl BufRead) -> io::Result<Self> {
+        let start = reader.fill_buf()?;
+        Ok(if start.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else {
+            Self::None
+        })
+    }
+
+    /// Chooses format by extension of the archive name, such as `archive.dca.gz`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Checks whether path has extension of DCA archive, possibly compressed, such as `archive.dca.zst`
+    pub fn is_archive_path(path: &Path) -> bool {
+        let path = match Self::from_path(path) {
+            Self::None => path,
+            _ => path.file_stem().map(Path::new).unwrap_or(path),
+        };
+        path.extension() == Some(OsStr::new("dca"))
+    }
+
+    fn unsupported(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("support of {:?} compression is not enabled", self),
+        )
+    }
+
+    /// Wraps reader of compressed stream, decompressing it
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+        match self {
+            Self::None => Ok(Box::new(reader)),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Ok(Box::new(io::BufReader::new(
+                flate2::bufread::MultiGzDecoder::new(reader),
+            ))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(io::BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(reader)?,
+            ))),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    /// Wraps writer, compressing everything written into it. Stream needs to be completed by [`Encoder::finish`].
+    pub fn encoder<W: Write>(self, writer: W) -> io::Result<Encoder<W>> {
+        Ok(Encoder(match self {
+            Self::None => EncoderKind::None(writer),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => EncoderKind::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => EncoderKind::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+            #[allow(unreachable_patterns)]
+            _ => return Err(self.unsupported()),
+        }))
+    }
+}
+
+/// Compression of individual entries, recorded by `codec` attribute of the extended format
+///
+/// Unlike [`Codec`], entries stay accessible individually, at the cost of lower compression ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum EntryCodec {
+    /// Payload is stored as is
+    #[default]
+    Stored,
+    /// Raw deflate stream
+    Deflate,
+    /// Zstandard frame
+    Zstd,
+}
+
+impl EntryCodec {
+    /// Name used as value of the `codec` attribute
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stored => "stored",
+            Self::Deflate => "deflate",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Parses value of the `codec` attribute
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Stored, Self::Deflate, Self::Zstd]
+            .iter()
+            .copied()
+            .find(|codec| codec.name() == name)
+    }
+
+    fn unsupported(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("support of {} entries is not enabled", self.name()),
+        )
+    }
+
+    /// Wraps reader of stored payload, decompressing it
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+        match self {
+            Self::Stored => Ok(Box::new(reader)),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => Ok(Box::new(io::BufReader::new(
+                flate2::bufread::DeflateDecoder::new(reader),
+            ))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(io::BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(reader)?,
+            ))),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    /// Wraps reader of original content, providing compressed payload
+    pub fn encoder<'a, R: BufRead + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        match self {
+            Self::Stored => Ok(Box::new(reader)),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => Ok(Box::new(flate2::bufread::DeflateEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Ok(Box::new(zstd::stream::read::Encoder::with_buffer(
+                reader, 0,
+            )?)),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+}
+
+/// Writer compressing data with one of [`Codec`]s
+pub struct Encoder<W: Write>(EncoderKind<W>);
+
+enum EncoderKind<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes end of the compressed stream and returns the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self.0 {
+            EncoderKind::None(w) => Ok(w),
+            #[cfg(feature = "gzip")]
+            EncoderKind::Gzip(w) => w.finish(),
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(w) => w.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            EncoderKind::None(w) => w.write(buf),
+            #[cfg(feature = "gzip")]
+            EncoderKind::Gzip(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(w) => w.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            EncoderKind::None(w) => w.flush(),
+            #[cfg(feature = "gzip")]
+            EncoderKind::Gzip(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Codec::detect(&mut &b"DCA\n"[..]).unwrap(), Codec::None);
+        assert_eq!(Codec::detect(&mut &b""[..]).unwrap(), Codec::None);
+        assert_eq!(
+            Codec::detect(&mut &b"\x1f\x8b\x08"[..]).unwrap(),
+            Codec::Gzip
+        );
+        assert_eq!(Codec::from_path(Path::new("a.dca.zst")), Codec::Zstd);
+        assert!(Codec::is_archive_path(Path::new("a.dca.gz")));
+        assert!(Codec::is_archive_path(Path::new("a.dca")));
+        assert!(!Codec::is_archive_path(Path::new("a.gz")));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let archive = b"DCA\nnotes\n5\nhello\n";
+        for codec in [Codec::None, Codec::Gzip, Codec::Zstd] {
+            let mut encoder = match codec.encoder(Vec::new()) {
+                Ok(encoder) => encoder,
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => continue,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+            encoder.write_all(archive).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            let mut reader = &compressed[..];
+            assert_eq!(Codec::detect(&mut reader).unwrap(), codec);
+            let mut decoded = Vec::new();
+            codec
+                .decoder(reader)
+                .unwrap()
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, archive);
+        }
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let data = b"hello hello hello hello";
+        assert_eq!(EntryCodec::from_name("zstd"), Some(EntryCodec::Zstd));
+        assert_eq!(EntryCodec::from_name("lzma"), None);
+        for codec in [EntryCodec::Stored, EntryCodec::Deflate, EntryCodec::Zstd] {
+            let mut encoded = Vec::new();
+            match codec.encoder(&data[..]) {
+                Ok(mut encoder) => encoder.read_to_end(&mut encoded).unwrap(),
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => continue,
+                Err(e) => panic!("Unexpected error {:?}", e),
+            };
+            let mut decoded = Vec::new();
+            codec
+                .decoder(&encoded[..])
+                .unwrap()
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+}
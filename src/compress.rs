@@ -1,12 +1,22 @@
 //! Handles creation of DCA archives
 
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, prelude::*};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::checksum::{Checksum, HashingReader};
+use crate::codec::{Codec, EntryCodec};
+use crate::decompress::{self, decompress_from_seekable, numbered_name, CallbackFileHandler};
 use crate::error::{
-    error, into_dca_filename, ArchiveError, FilePosition, Handler as ErrorHandler, Result,
+    error, into_dca_filename, into_dca_path, warn, ArchiveError, DcaFilenameError, FilePosition,
+    Handler as ErrorHandler, Result,
 };
+use crate::index::{locate_index, read_index, write_index, CountingWriter, IndexEntry, INDEX_NAME};
+use crate::metadata::Metadata;
+use crate::tempfile::TempFile;
+use crate::writer::{entry_name, write_entry};
 
 /// [`ErrorHandler`] that fails on every condition, logging each encountered problem
 pub struct DefaultErrorHandler<'a> {
@@ -37,6 +47,15 @@ impl<'a> DefaultErrorHandler<'a> {
             InvalidDcaFilename(fname, load_err) => {
                 error!("File {:?} does not have a legal filename and cannot be added into the archive, due to following error: {}", fname, load_err);
             }
+            DuplicateEntry(name) => {
+                error!(
+                    "Archive {:?} already contains entry named {:?}",
+                    self.archive_name, name
+                );
+            }
+            FileExists(path) => {
+                error!("Archive {:?} already exists, leaving it untouched", path);
+            }
             err => error!(
                 "Creation of archive {:?} failed due to error {:?}",
                 self.archive_name, err
@@ -54,13 +73,93 @@ impl<'a> ErrorHandler for DefaultErrorHandler<'a> {
 
 /// Representation of contents and metadata of one file intended to be added into archive
 pub struct FileDescriptor<'a, R: BufRead> {
-    /// Path to file about to be added. Only filename is perserved in the archive
+    /// Path to file about to be added. Only filename is perserved in the archive, unless [`Self::name`] is set
     pub path: &'a Path,
+    /// Name under which the file is stored in the archive instead of filename of [`Self::path`]
+    pub name: Option<&'a str>,
     /// Reader that shall provide exactly [`Self::len`] bytes of data. [`std::io::Take`] adapter can be used
     /// if the source cannot guarantee that
     pub reader: R,
     /// Size of the file. Also represents how many bytes from reader should be available from the reader
     pub len: FilePosition,
+    /// Modification time, permissions and such. Stored only if enabled by [`EntryOptions::metadata`]
+    pub metadata: Metadata,
+    /// Checksum of the content, of the kind set by [`EntryOptions::checksum`], if the handler could compute it
+    /// upfront (such as by reading the file twice). Otherwise content is read whole before it's written to find it out
+    pub checksum: Option<String>,
+}
+
+/// Treatment of files whose name is already present in the archive
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EntryDuplicatePolicy {
+    /// File is reported as [`ArchiveError::DuplicateEntry`] and isn't added unless the [`ErrorHandler`] tolerates it
+    Reject,
+    /// File is added with a warning
+    #[default]
+    Warn,
+    /// File is added under numbered name, see [`numbered_name`]
+    Rename,
+}
+
+/// Options of entries written by [`compress_into`], shared by file handlers of this module
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::checksum::Checksum;
+/// use dca::compress::{compress_files_with, DefaultFileHandler, EntryOptions};
+///
+/// let options = EntryOptions::new().metadata(true).checksum(Some(Checksum::Crc32));
+/// let mut fhandler = DefaultFileHandler::new(&["notes.txt"]).options(options);
+/// compress_files_with("archive.dca", &mut fhandler, false).expect("failed to create the archive");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EntryOptions {
+    duplicates: EntryDuplicatePolicy,
+    compression: EntryCodec,
+    metadata: bool,
+    checksum: Option<Checksum>,
+    index: bool,
+}
+
+impl EntryOptions {
+    /// Creates default options, storing plain entries in archive of basic format
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets treatment of files with names already present in the archive
+    pub fn duplicates(mut self, policy: EntryDuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+    /// Sets compression of individual entries. Anything but [`EntryCodec::Stored`] needs archive
+    /// in extended format
+    pub fn compression(mut self, codec: EntryCodec) -> Self {
+        self.compression = codec;
+        self
+    }
+    /// Sets whether [`FileDescriptor::metadata`] is stored. Needs archive in extended format
+    pub fn metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+    /// Sets checksum of each entry, see [`crate::checksum`]. Needs archive in extended format.
+    /// As the checksum precedes the payload, handlers of this module read files twice, unless the entries are compressed.
+    /// Content of other handlers is read whole first, unless they provide [`FileDescriptor::checksum`]
+    pub fn checksum(mut self, checksum: Option<Checksum>) -> Self {
+        self.checksum = checksum;
+        self
+    }
+    /// Sets whether [`compress_into`] ends the archive with index of its entries, see [`crate::index`].
+    /// Files named [`INDEX_NAME`] are then rejected with [`DcaFilenameError::Reserved`]
+    pub fn index(mut self, index: bool) -> Self {
+        self.index = index;
+        self
+    }
+    /// Returns true if entries need archive in extended format
+    pub fn is_extended(&self) -> bool {
+        self.compression != EntryCodec::Stored || self.metadata || self.checksum.is_some()
+    }
 }
 
 /// Allows full customization of [`compress_into`] input file handling.
@@ -80,11 +179,26 @@ pub trait FileHandler {
     fn add_file<Callback>(&mut self, compress: Callback) -> Result<Option<()>>
     where
         Callback: FnOnce(FileDescriptor<'_, Self::Reader>) -> Result<()>;
+
+    /// Options of written entries. If they need archive in extended format,
+    /// [`compress_into`] writes the archive that way
+    fn options(&self) -> EntryOptions {
+        EntryOptions::default()
+    }
+
+    /// Whether stored names may be relative paths, see [`into_dca_path`]
+    fn paths(&self) -> bool {
+        false
+    }
 }
 
 /// Handler for [`compress_into`], feeding it list of files.
 ///
 /// This class is responsible for whole file management (opening/closing).
+///
+/// Path [`STDIN_PATH`] stands for standard input, stored under name set by [`Self::stdin_name`].
+/// Inputs that can't report their size (standard input, pipes, FIFOs, special files), are read whole first,
+/// into memory or into a temporary file if they're large.
 pub struct DefaultFileHandler<I>
 // where
 //     I: Iterator,
@@ -92,6 +206,8 @@ pub struct DefaultFileHandler<I>
 {
     /// Iterable of files (file paths)
     files: I,
+    options: EntryOptions,
+    stdin_name: String,
 }
 impl<I> DefaultFileHandler<I> {
     /// Constructor. Takes an iterable of paths or equivalent
@@ -103,8 +219,20 @@ impl<I> DefaultFileHandler<I> {
     {
         Self {
             files: files.into_iter(),
+            options: EntryOptions::default(),
+            stdin_name: DEFAULT_STDIN_NAME.to_owned(),
         }
     }
+    /// Sets options of written entries
+    pub fn options(mut self, options: EntryOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Sets name of the entry read from standard input. Defaults to [`DEFAULT_STDIN_NAME`]
+    pub fn stdin_name(mut self, name: impl Into<String>) -> Self {
+        self.stdin_name = name.into();
+        self
+    }
 }
 
 impl<I> FileHandler for DefaultFileHandler<I>
@@ -112,29 +240,346 @@ where
     I: Iterator,
     I::Item: AsRef<Path>,
 {
-    type Reader = io::BufReader<fs::File>;
+    type Reader = io::BufReader<Source>;
+    fn options(&self) -> EntryOptions {
+        self.options
+    }
     fn add_file<Callback>(&mut self, compress: Callback) -> Result<Option<()>>
     where
         Callback: FnOnce(FileDescriptor<'_, Self::Reader>) -> Result<()>,
     {
-        use ArchiveError as E;
-
         let file_path = match self.files.next() {
             None => return Ok(None),
             Some(f) => f,
         };
         let file_path = file_path.as_ref();
-        let bad_io = |e| E::BadFileIo(file_path.to_owned(), e);
+        let (mut reader, file_len, metadata) = open_file(file_path)?;
+        let checksum = precompute_checksum(&mut reader, file_len, self.options)
+            .map_err(|e| ArchiveError::BadFileIo(file_path.to_owned(), e))?;
+
+        compress(FileDescriptor {
+            path: file_path,
+            name: if file_path == Path::new(STDIN_PATH) {
+                Some(&self.stdin_name)
+            } else {
+                None
+            },
+            reader,
+            len: file_len,
+            metadata,
+            checksum,
+        })?;
+        Ok(Some(()))
+    }
+}
+
+/// Lazily read list of paths, see [`FileListHandler`]
+pub struct FileList<R> {
+    reader: R,
+    delimiter: u8,
+    /// Set if the list itself is read from standard input, which then can't be listed
+    from_stdin: bool,
+    /// Failure of reading the list, which ends the iteration
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> FileList<R> {
+    fn read_path(&mut self) -> io::Result<Option<PathBuf>> {
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if self.reader.read_until(self.delimiter, &mut buf)? == 0 {
+                return Ok(None);
+            }
+            if buf.last() == Some(&self.delimiter) {
+                buf.pop();
+            }
+            if !buf.is_empty() {
+                break;
+            }
+        }
+        #[cfg(unix)]
+        let path = {
+            use std::os::unix::ffi::OsStringExt;
+            std::ffi::OsString::from_vec(buf)
+        };
+        #[cfg(not(unix))]
+        let path =
+            String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if self.from_stdin && path == STDIN_PATH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "standard input can't be listed in list read from it",
+            ));
+        }
+        Ok(Some(path.into()))
+    }
+}
+
+impl<R: BufRead> Iterator for FileList<R> {
+    type Item = PathBuf;
+    fn next(&mut self) -> Option<PathBuf> {
+        if self.error.is_some() {
+            return None;
+        }
+        self.read_path().unwrap_or_else(|e| {
+            self.error = Some(e);
+            None
+        })
+    }
+}
+
+/// Handler for [`compress_into`] reading list of files to add from `reader` as they're needed,
+/// so that the list is never held in memory whole. Otherwise works as [`DefaultFileHandler`].
+///
+/// Paths are separated by newlines, or by NUL characters if `null` is set. Empty ones are skipped.
+/// Failure of reading the list is reported as [`ArchiveError::BadFileIo`] of `list_name`.
+/// If `list_name` is [`STDIN_PATH`], the list is read from standard input, so it can't contain [`STDIN_PATH`] itself.
+pub struct FileListHandler<R> {
+    inner: DefaultFileHandler<FileList<R>>,
+    list_name: PathBuf,
+}
+
+impl<R: BufRead> FileListHandler<R> {
+    /// Constructor. `list_name` is used for error reporting
+    pub fn new(reader: R, list_name: impl Into<PathBuf>, null: bool) -> Self {
+        let list_name = list_name.into();
+        Self {
+            inner: DefaultFileHandler::new(FileList {
+                reader,
+                delimiter: if null { b'\0' } else { b'\n' },
+                from_stdin: list_name == Path::new(STDIN_PATH),
+                error: None,
+            }),
+            list_name,
+        }
+    }
+    /// Sets options of written entries
+    pub fn options(mut self, options: EntryOptions) -> Self {
+        self.inner = self.inner.options(options);
+        self
+    }
+    /// Sets name of the entry read from standard input, see [`DefaultFileHandler::stdin_name`]
+    pub fn stdin_name(mut self, name: impl Into<String>) -> Self {
+        self.inner = self.inner.stdin_name(name);
+        self
+    }
+}
+
+impl<R: BufRead> FileHandler for FileListHandler<R> {
+    type Reader = io::BufReader<Source>;
+    fn options(&self) -> EntryOptions {
+        self.inner.options
+    }
+    fn add_file<Callback>(&mut self, compress: Callback) -> Result<Option<()>>
+    where
+        Callback: FnOnce(FileDescriptor<'_, Self::Reader>) -> Result<()>,
+    {
+        match self.inner.add_file(compress)? {
+            Some(()) => Ok(Some(())),
+            None => match self.inner.files.error.take() {
+                Some(e) => Err(ArchiveError::BadFileIo(self.list_name.clone(), e)),
+                None => Ok(None),
+            },
+        }
+    }
+}
+
+/// Path standing for standard input in lists of files to compress
+pub const STDIN_PATH: &str = "-";
+/// Default name of entry read from standard input
+pub const DEFAULT_STDIN_NAME: &str = "stdin";
+/// Inputs of unknown size up to this size are spooled into memory, larger ones into temporary file
+const SPOOL_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Contents of file about to be added by [`DefaultFileHandler`] or [`NamedFileHandler`]
+pub struct Source(SourceKind);
+
+enum SourceKind {
+    /// Regular file read directly
+    File(File),
+    /// Input spooled into memory
+    Memory(io::Cursor<Vec<u8>>),
+    /// Input spooled into temporary file
+    Spooled(TempFile),
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            SourceKind::File(file) => file.read(buf),
+            SourceKind::Memory(data) => data.read(buf),
+            SourceKind::Spooled(temp) => temp.file().read(buf),
+        }
+    }
+}
+
+impl Seek for Source {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<FilePosition> {
+        match &mut self.0 {
+            SourceKind::File(file) => file.seek(pos),
+            SourceKind::Memory(data) => data.seek(pos),
+            SourceKind::Spooled(temp) => temp.file().seek(pos),
+        }
+    }
+}
+
+/// Computes checksum of `len` bytes of `reader` for entries stored as is and rewinds it,
+/// so that the content doesn't have to be read whole before it's written
+fn precompute_checksum(
+    reader: &mut io::BufReader<Source>,
+    len: FilePosition,
+    options: EntryOptions,
+) -> io::Result<Option<String>> {
+    let checksum = match options.checksum {
+        Some(checksum) if options.compression == EntryCodec::Stored => checksum,
+        _ => return Ok(None),
+    };
+    let mut hashing = HashingReader::new(reader.by_ref().take(len), Some(checksum.hasher()?));
+    io::copy(&mut hashing, &mut io::sink())?;
+    let digest = hashing.finish();
+    reader.rewind()?;
+    Ok(digest)
+}
+
+/// Reads whole `reader` to find out its length, keeping up to `limit` bytes in memory
+fn spool(mut reader: impl Read, limit: usize) -> io::Result<(Source, FilePosition)> {
+    let mut buf = Vec::new();
+    reader
+        .by_ref()
+        .take(limit as FilePosition + 1)
+        .read_to_end(&mut buf)?;
+    if buf.len() <= limit {
+        let len = buf.len() as FilePosition;
+        return Ok((Source(SourceKind::Memory(io::Cursor::new(buf))), len));
+    }
+
+    // Spooled content may be sensitive, other users of the shared directory shouldn't see it
+    let mut temp = TempFile::new_private(&std::env::temp_dir().join("dca-spool"))?;
+    temp.file().write_all(&buf)?;
+    let len = buf.len() as FilePosition + io::copy(&mut reader, temp.file())?;
+    temp.file().seek(io::SeekFrom::Start(0))?;
+    Ok((Source(SourceKind::Spooled(temp)), len))
+}
+
+/// Compresses `len` bytes of `reader` with `codec`, spooling the result to find out its length.
+/// Checksum of the original content is computed along the way, if requested.
+/// Fails as [`write_entry`] does if the reader provides less or more than `len` bytes.
+fn encode(
+    codec: EntryCodec,
+    checksum: Option<Checksum>,
+    reader: &mut impl BufRead,
+    len: FilePosition,
+) -> io::Result<(Source, FilePosition, Option<String>)> {
+    let hasher = checksum.map(Checksum::hasher).transpose()?;
+    let mut limited = HashingReader::new((&mut *reader).take(len), hasher);
+    let (encoded, stored_len) = spool(codec.encoder(&mut limited)?, SPOOL_MEMORY_LIMIT)?;
+    if limited.get_ref().limit() != 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let digest = limited.finish();
+    if !reader.fill_buf()?.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("source provides more than declared {} bytes", len),
+        ));
+    }
+    Ok((encoded, stored_len, digest))
+}
+
+/// Opens file for reading, returning it along with its length and metadata
+///
+/// Inputs of unknown size are spooled, see [`DefaultFileHandler`]. Standard input has no metadata.
+pub(crate) fn open_file(path: &Path) -> Result<(io::BufReader<Source>, FilePosition, Metadata)> {
+    let bad_io = |e| ArchiveError::BadFileIo(path.to_owned(), e);
+
+    if path == Path::new(STDIN_PATH) {
+        let (source, len) = spool(io::stdin().lock(), SPOOL_MEMORY_LIMIT).map_err(bad_io)?;
+        return Ok((io::BufReader::new(source), len, Metadata::default()));
+    }
+    let mut file = File::open(path).map_err(bad_io)?;
+    let meta = file.metadata().map_err(bad_io)?;
+    // Some special files (such as in procfs) pose as empty regular files
+    let (source, len) = if meta.is_file() && meta.len() > 0 {
+        let len = file.seek(io::SeekFrom::End(0)).map_err(bad_io)?;
+        file.seek(io::SeekFrom::Start(0)).map_err(bad_io)?;
+        (Source(SourceKind::File(file)), len)
+    } else {
+        spool(file, SPOOL_MEMORY_LIMIT).map_err(bad_io)?
+    };
+    Ok((io::BufReader::new(source), len, Metadata::from_fs(&meta)))
+}
+
+/// Variant of [`DefaultFileHandler`] that takes pairs of paths and names under which they're stored,
+/// such as ones collected by [`crate::walk::Walk`]
+///
+/// Names may be relative paths if enabled by [`Self::paths`]. Inputs are opened the same way as
+/// by [`DefaultFileHandler`], including [`STDIN_PATH`].
+pub struct NamedFileHandler<I> {
+    files: I,
+    options: EntryOptions,
+    paths: bool,
+}
+impl<I> NamedFileHandler<I> {
+    /// Constructor. Takes an iterable of `(path, name)` pairs
+    pub fn new<II, P, S>(files: II) -> Self
+    where
+        II: IntoIterator<IntoIter = I>,
+        I: Iterator<Item = (P, S)>,
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        Self {
+            files: files.into_iter(),
+            options: EntryOptions::default(),
+            paths: false,
+        }
+    }
+    /// Sets options of written entries
+    pub fn options(mut self, options: EntryOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Allows names to be relative paths, see [`into_dca_path`]
+    pub fn paths(mut self, paths: bool) -> Self {
+        self.paths = paths;
+        self
+    }
+}
 
-        let file = File::open(file_path).map_err(bad_io)?;
-        let mut reader = io::BufReader::new(file);
-        let file_len = reader.seek(io::SeekFrom::End(0)).map_err(bad_io)?;
-        reader.seek(io::SeekFrom::Start(0)).map_err(bad_io)?;
+impl<I, P, S> FileHandler for NamedFileHandler<I>
+where
+    I: Iterator<Item = (P, S)>,
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    type Reader = io::BufReader<Source>;
+    fn options(&self) -> EntryOptions {
+        self.options
+    }
+    fn paths(&self) -> bool {
+        self.paths
+    }
+    fn add_file<Callback>(&mut self, compress: Callback) -> Result<Option<()>>
+    where
+        Callback: FnOnce(FileDescriptor<'_, Self::Reader>) -> Result<()>,
+    {
+        let (file_path, name) = match self.files.next() {
+            None => return Ok(None),
+            Some(f) => f,
+        };
+        let file_path = file_path.as_ref();
+        let (mut reader, file_len, metadata) = open_file(file_path)?;
+        let checksum = precompute_checksum(&mut reader, file_len, self.options)
+            .map_err(|e| ArchiveError::BadFileIo(file_path.to_owned(), e))?;
 
         compress(FileDescriptor {
             path: file_path,
+            name: Some(name.as_ref()),
             reader,
             len: file_len,
+            metadata,
+            checksum,
         })?;
         Ok(Some(()))
     }
@@ -157,50 +602,185 @@ pub fn compress_into(
     writer: &mut impl Write,
     handle_file: &mut impl FileHandler,
     handle_err: &mut impl ErrorHandler,
+) -> Result<()> {
+    let header: &[u8] = if handle_file.options().is_extended() {
+        b"DCA2\n"
+    } else {
+        b"DCA\n"
+    };
+    let mut writer = CountingWriter::new(writer);
+    writer.write_all(header).map_err(ArchiveError::ArchiveIo)?;
+    let mut index = if handle_file.options().index {
+        Some(Vec::new())
+    } else {
+        None
+    };
+    append_entries(
+        &mut writer,
+        handle_file,
+        handle_err,
+        HashSet::new(),
+        index.as_mut(),
+    )?;
+    match index {
+        Some(index) => write_index(&mut writer, &index),
+        None => Ok(()),
+    }
+}
+
+/// Variant of [`compress_into`] that continues existing archive instead of starting new one.
+///
+/// Writer is expected to be positioned right after the last complete entry of the archive,
+/// only new entries are written. Index of the archive isn't written, see [`crate::index::locate_index`].
+///
+/// Entries already present in the archive aren't considered by [`EntryDuplicatePolicy`].
+/// `extended` tells whether the archive is in extended format, as entries needing it (see [`EntryOptions::is_extended`])
+/// can't be appended otherwise. Such attempt fails with [`ArchiveError::ArchiveIo`] before anything is written.
+///
+/// Also see [append_files] for more hands-off interface.
+pub fn append_into(
+    writer: &mut impl Write,
+    handle_file: &mut impl FileHandler,
+    handle_err: &mut impl ErrorHandler,
+    extended: bool,
+) -> Result<()> {
+    check_extended(handle_file, extended)?;
+    append_entries(
+        &mut CountingWriter::new(writer),
+        handle_file,
+        handle_err,
+        HashSet::new(),
+        None,
+    )
+}
+
+/// Fails if entries of `handle_file` need extended format, but the archive isn't `extended`
+fn check_extended(handle_file: &impl FileHandler, extended: bool) -> Result<()> {
+    if handle_file.options().is_extended() && !extended {
+        return Err(ArchiveError::ArchiveIo(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "entry options need archive in extended format",
+        )));
+    }
+    Ok(())
+}
+
+/// Implementation of [`append_into`], `names` contains names already present in the archive.
+/// Positions of written entries are collected into `index`, if given.
+fn append_entries<W: Write>(
+    writer: &mut CountingWriter<W>,
+    handle_file: &mut impl FileHandler,
+    handle_err: &mut impl ErrorHandler,
+    mut names: HashSet<String>,
+    mut index: Option<&mut Vec<IndexEntry>>,
 ) -> Result<()> {
     use ArchiveError as E;
 
-    writer.write_all(b"DCA\n").map_err(E::ArchiveIo)?;
+    let EntryOptions {
+        duplicates,
+        compression,
+        metadata: store_metadata,
+        checksum,
+        ..
+    } = handle_file.options();
+    let validate = if handle_file.paths() {
+        into_dca_path
+    } else {
+        into_dca_filename
+    };
     loop {
         match handle_file.add_file(|file| {
             let FileDescriptor {
                 mut reader,
                 path,
+                name,
                 len,
+                metadata,
+                checksum: digest,
             } = file;
 
-            // Validate filename
-            let fname = path
-                .file_name()
-                .ok_or_else(|| E::BadFileIo(path.to_owned(), io::ErrorKind::NotFound.into()))?;
-
-            let name =
-                into_dca_filename(fname).map_err(|e| E::InvalidDcaFilename(path.to_owned(), e))?;
-
-            writer
-                .write_fmt(format_args!("{}\n{}\n", name, len))
-                .map_err(E::ArchiveIo)?;
-
-            let mut stored_len = 0;
-            loop {
-                let buf = reader
-                    .fill_buf()
-                    .map_err(|e| E::BadFileIo(path.to_owned(), e))?;
-                let new_len = buf.len();
-                if new_len == 0 {
-                    if stored_len as FilePosition != len {
-                        return Err(E::BadFileIo(
-                            path.to_owned(),
-                            io::ErrorKind::UnexpectedEof.into(),
-                        ));
+            let mut name = match name {
+                Some(name) => validate(OsStr::new(name))
+                    .map_err(|e| E::InvalidDcaFilename(path.to_owned(), e))?,
+                None => entry_name(path)?,
+            }
+            .to_owned();
+            if index.is_some() && name == INDEX_NAME {
+                return Err(E::InvalidDcaFilename(
+                    path.to_owned(),
+                    DcaFilenameError::Reserved,
+                ));
+            }
+            if names.contains(&name) {
+                match duplicates {
+                    EntryDuplicatePolicy::Reject => return Err(E::DuplicateEntry(name)),
+                    EntryDuplicatePolicy::Warn => {
+                        warn!(
+                            "Archive already contains entry {:?}, adding another one",
+                            name
+                        );
+                    }
+                    EntryDuplicatePolicy::Rename => {
+                        let new_name = (2..)
+                            .map(|n| numbered_name(&name, n))
+                            .find(|new_name| !names.contains(new_name))
+                            .expect("unbounded range");
+                        warn!(
+                            "Archive already contains entry {:?}, adding {:?} instead",
+                            name, new_name
+                        );
+                        name = new_name;
                     }
-                    break;
                 }
-                writer.write_all(buf).map_err(E::ArchiveIo)?;
-                stored_len += new_len;
-                reader.consume(new_len);
             }
-            writer.write_all(b"\n").map_err(E::ArchiveIo)?;
+            let mut attributes = if store_metadata {
+                metadata.to_attributes()
+            } else {
+                Vec::new()
+            };
+            // Empty entries aren't worth compressing
+            let codec = if len == 0 {
+                EntryCodec::Stored
+            } else {
+                compression
+            };
+            let offset = writer.count();
+            let stored_len = if codec == EntryCodec::Stored
+                && (checksum.is_none() || digest.is_some())
+            {
+                if let (Some(checksum), Some(digest)) = (checksum, digest) {
+                    attributes.push((checksum.name().to_owned(), digest));
+                }
+                write_entry(writer, &name, &attributes, len, &mut reader, path)?;
+                len
+            } else {
+                let (encoded, stored_len, digest) = encode(codec, checksum, &mut reader, len)
+                    .map_err(|e| E::BadFileIo(path.to_owned(), e))?;
+                if let (Some(checksum), Some(digest)) = (checksum, digest) {
+                    attributes.push((checksum.name().to_owned(), digest));
+                }
+                if codec != EntryCodec::Stored {
+                    attributes.push(("codec".to_owned(), codec.name().to_owned()));
+                    attributes.push(("size".to_owned(), len.to_string()));
+                }
+                write_entry(
+                    writer,
+                    &name,
+                    &attributes,
+                    stored_len,
+                    &mut io::BufReader::new(encoded),
+                    path,
+                )?;
+                stored_len
+            };
+            if let Some(index) = &mut index {
+                index.push(IndexEntry {
+                    name: name.clone(),
+                    offset,
+                    len: stored_len,
+                });
+            }
+            names.insert(name);
             Ok(())
         }) {
             Ok(None) => break,
@@ -218,11 +798,12 @@ pub fn compress_into(
 /// Compresses list of files into new DCA archive.
 ///
 /// The new archive will be created at `archive_name` path,
-/// (_not_ creating nonexisting directories).
+/// (_not_ creating nonexisting directories), replacing existing file.
+/// Archive is written into temporary file next to it first and moved into place only when complete.
 /// Accepts list of paths to individual files, but as DCA format
 /// is flat, no directories are permitted. Multiple files with same name
 /// can be technically stored in the archive, but there's no additional
-/// metadata to disambiguate them.
+/// metadata to disambiguate them - they're added with a warning, see [`EntryDuplicatePolicy`].
 ///
 /// May fail for various I/O reasons, see [`ArchiveError`] for details. Fails on first error - if
 /// you're interested in more tuneable compression, see [`compress_into`].
@@ -242,21 +823,194 @@ where
 {
     let archive_name = archive_name.as_ref();
 
-    let mut fhandler = DefaultFileHandler::new(files);
+    compress_files_with(archive_name, &mut DefaultFileHandler::new(files), false)
+}
+
+/// Variant of [`compress_files`] with custom [`FileHandler`], such as [`NamedFileHandler`]
+///
+/// Archive named with `.gz` or `.zst` extension is wrapped in compressed stream, see [`crate::codec`].
+///
+/// If `no_clobber` is set, existing file at `archive_name` isn't replaced and [`ArchiveError::FileExists`]
+/// is returned instead. That holds even for file created while the archive is being written.
+pub fn compress_files_with(
+    archive_name: impl AsRef<Path>,
+    fhandler: &mut impl FileHandler,
+    no_clobber: bool,
+) -> Result<()> {
+    let archive_name = archive_name.as_ref();
     let mut ehandler = DefaultErrorHandler::new(archive_name);
 
-    let arch = File::create(archive_name).map_err(|e| {
-        let e = ArchiveError::ArchiveIo(e);
-        ehandler.on_fatal(&e);
-        e
-    })?;
-    let mut writer = io::BufWriter::new(arch);
-    compress_into(&mut writer, &mut fhandler, &mut ehandler).map_err(|e| {
-        ehandler.on_fatal(&e);
-        if let Err(io_err) = fs::remove_file(archive_name) {
-            error!("Removal of incorrectly created archive {:?} failed with error {}, please remove it manually.", archive_name, io_err);
+    // Archive is built under temporary name, so that failure never leaves incomplete archive behind
+    let mut create = |ehandler: &mut DefaultErrorHandler| {
+        // Checked upfront just to fail early, the archive is persisted without replacing anyway
+        if no_clobber && fs::symlink_metadata(archive_name).is_ok() {
+            return Err(ArchiveError::FileExists(archive_name.to_owned()));
+        }
+        let mut temp = TempFile::new_sibling(archive_name).map_err(ArchiveError::ArchiveIo)?;
+        let mut writer = Codec::from_path(archive_name)
+            .encoder(io::BufWriter::new(temp.file()))
+            .map_err(ArchiveError::ArchiveIo)?;
+        compress_into(&mut writer, fhandler, ehandler)?;
+        writer
+            .finish()
+            .and_then(|mut writer| writer.flush())
+            .map_err(ArchiveError::ArchiveIo)?;
+        if !no_clobber {
+            return temp.persist(archive_name).map_err(ArchiveError::ArchiveIo);
+        }
+        temp.persist_new(archive_name).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => ArchiveError::FileExists(archive_name.to_owned()),
+            _ => ArchiveError::ArchiveIo(e),
+        })
+    };
+    create(&mut ehandler).inspect_err(|e| ehandler.on_fatal(e))
+}
+
+/// Appends list of files to the end of existing DCA archive.
+///
+/// Structure of the archive is checked first, failing with [`ArchiveError::CorruptedArchive`] if
+/// it's malformed. New entries are then written right after the last one.
+///
+/// Accepts the same inputs as [`compress_files`]. If adding any file fails, archive is restored
+/// to its original content.
+/// Index of the archive (see [`crate::index`]) is replaced by new one covering the new entries too.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::compress::append_files;
+///
+/// append_files(&["later.txt"], "archive.dca")
+///     .expect("failed to extend the archive");
+/// ```
+pub fn append_files<PathIter>(files: PathIter, archive_name: impl AsRef<Path>) -> Result<()>
+where
+    PathIter: IntoIterator,
+    PathIter::Item: AsRef<Path>,
+{
+    append_files_with(archive_name, &mut DefaultFileHandler::new(files))
+}
+
+/// Variant of [`append_files`] taking files from custom [`FileHandler`], such as one with [`EntryOptions`] set.
+///
+/// [`EntryOptions::index`] is ignored, as the index is kept only if the archive already has one.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::checksum::Checksum;
+/// use dca::compress::{append_files_with, DefaultFileHandler, EntryOptions};
+///
+/// let options = EntryOptions::new().checksum(Some(Checksum::Crc32));
+/// let mut fhandler = DefaultFileHandler::new(&["later.txt"]).options(options);
+/// append_files_with("archive.dca", &mut fhandler).expect("failed to extend the archive");
+/// ```
+pub fn append_files_with(
+    archive_name: impl AsRef<Path>,
+    fhandler: &mut impl FileHandler,
+) -> Result<()> {
+    let archive_name = archive_name.as_ref();
+
+    let mut ehandler = DefaultErrorHandler::new(archive_name);
+
+    let mut arch = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(archive_name)
+        .map_err(|e| {
+            let e = ArchiveError::ArchiveIo(e);
+            ehandler.on_fatal(&e);
+            e
+        })?;
+
+    // Locate end of the last entry, or the index that new entries replace
+    let mut names = HashSet::new();
+    let (end, extended, index) = {
+        let mut reader = io::BufReader::new(&mut arch);
+        // Compressed stream can't be extended in place
+        Codec::detect(&mut reader)
+            .and_then(|codec| match codec {
+                Codec::None => Ok(()),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "appending to compressed archive is not supported",
+                )),
+            })
+            .map_err(ArchiveError::ArchiveIo)
+            .and_then(|()| {
+                decompress_from_seekable(
+                    &mut reader,
+                    &mut CallbackFileHandler(|name, _, _| {
+                        names.insert(name.to_owned());
+                        Ok(())
+                    }),
+                    &decompress::DefaultErrorHandler::new(archive_name),
+                )
+            })
+            .and_then(|()| {
+                let end = reader.stream_position().map_err(ArchiveError::ArchiveIo)?;
+                let mut header = [0u8; 5];
+                reader.rewind().map_err(ArchiveError::ArchiveIo)?;
+                let extended = reader.read_exact(&mut header).is_ok() && &header == b"DCA2\n";
+                reader.rewind().map_err(ArchiveError::ArchiveIo)?;
+                let index = match locate_index(&mut reader)? {
+                    Some(offset) => {
+                        reader.rewind().map_err(ArchiveError::ArchiveIo)?;
+                        Some((offset, read_index(&mut reader)?))
+                    }
+                    None => None,
+                };
+                Ok((end, extended, index))
+            })
+            .inspect_err(|e| ehandler.on_fatal(e))?
+    };
+    let start = index.as_ref().map_or(end, |(offset, _)| *offset);
+    // Replaced index is kept aside, so that it can be put back on failure
+    let mut replaced = Vec::new();
+    arch.seek(io::SeekFrom::Start(start))
+        .and_then(|_| arch.read_to_end(&mut replaced))
+        .map_err(ArchiveError::ArchiveIo)
+        .inspect_err(|e| ehandler.on_fatal(e))?;
+    let mut index = index.map(|(_, entries)| {
+        entries.unwrap_or_else(|| {
+            warn!(
+                "Index of archive {:?} can't be read, dropping it",
+                archive_name
+            );
+            Vec::new()
+        })
+    });
+
+    let mut append = |arch: &mut File, ehandler: &mut DefaultErrorHandler| {
+        check_extended(fhandler, extended)?;
+        arch.seek(io::SeekFrom::Start(start))
+            .map_err(ArchiveError::ArchiveIo)?;
+        let mut writer = CountingWriter::with_count(io::BufWriter::new(&mut *arch), start);
+        append_entries(
+            &mut writer,
+            fhandler,
+            ehandler,
+            std::mem::take(&mut names),
+            index.as_mut(),
+        )?;
+        if let Some(index) = &index {
+            write_index(&mut writer, index)?;
+        }
+        writer.flush().map_err(ArchiveError::ArchiveIo)?;
+        drop(writer);
+        // New content may be shorter than the replaced index
+        let len = arch.stream_position().map_err(ArchiveError::ArchiveIo)?;
+        arch.set_len(len).map_err(ArchiveError::ArchiveIo)
+    };
+    append(&mut arch, &mut ehandler).inspect_err(|e| {
+        ehandler.on_fatal(e);
+        let restored = arch
+            .set_len(start)
+            .and_then(|()| arch.seek(io::SeekFrom::Start(start)))
+            .and_then(|_| arch.write_all(&replaced));
+        if let Err(io_err) = restored {
+            error!("Restoring archive {:?} to its original content failed with error {}, please truncate it to {} bytes manually.", archive_name, io_err, start);
         }
-        e
     })
 }
 
@@ -264,7 +1018,6 @@ where
 mod tests {
     use super::*;
 
-    use crate::error::DcaFilenameError;
     use assert_fs::{prelude::*, TempDir};
     use std::ffi::OsStr;
     use std::io::BufReader;
@@ -340,6 +1093,44 @@ mod tests {
         assert_eq!(out, contents);
     }
 
+    #[test]
+    fn test_duplicates() {
+        let dir = make_dir();
+        dir.child("a/notes.txt").write_str("1").unwrap();
+        dir.child("b/notes.txt").write_str("2").unwrap();
+        dir.child("c/notes.txt").write_str("3").unwrap();
+        let paths = [
+            dir.child("a/notes.txt"),
+            dir.child("b/notes.txt"),
+            dir.child("c/notes.txt"),
+        ];
+
+        let mut out = Vec::<u8>::new();
+        compress_into(&mut out, &mut files(&paths), &mut std_errors()).unwrap();
+        assert_eq!(
+            out,
+            b"DCA\nnotes.txt\n1\n1\nnotes.txt\n1\n2\nnotes.txt\n1\n3\n"
+        );
+
+        let mut out = Vec::<u8>::new();
+        let mut fhandler =
+            files(&paths).options(EntryOptions::new().duplicates(EntryDuplicatePolicy::Rename));
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert_eq!(
+            out,
+            b"DCA\nnotes.txt\n1\n1\nnotes (2).txt\n1\n2\nnotes (3).txt\n1\n3\n" as &[u8]
+        );
+
+        let mut out = Vec::<u8>::new();
+        let mut fhandler =
+            files(&paths).options(EntryOptions::new().duplicates(EntryDuplicatePolicy::Reject));
+        match compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap_err() {
+            ArchiveError::DuplicateEntry(name) if name == "notes.txt" => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+        assert_eq!(out, b"DCA\nnotes.txt\n1\n1\n");
+    }
+
     #[test]
     fn test_errors() {
         let dir = make_dir();
@@ -372,10 +1163,7 @@ mod tests {
             match bad {
                 ArchiveError::InvalidDcaFilename(_, DcaFilenameError::InvalidChar('\n', 0)) => (),
                 ArchiveError::BadFileIo(_, io_err)
-                    if io_err.kind() == io::ErrorKind::InvalidInput =>
-                {
-                    ()
-                }
+                    if io_err.kind() == io::ErrorKind::InvalidInput => {}
                 e => panic!("Unexpected error {:?}", e),
             }
         }
@@ -431,6 +1219,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_len() {
+        let encode = |content: &[u8], len| {
+            encode(
+                EntryCodec::Stored,
+                Some(Checksum::Crc32),
+                &mut { content },
+                len,
+            )
+            .map(|(_, stored_len, digest)| (stored_len, digest))
+        };
+        assert_eq!(
+            encode(b"hello", 5).unwrap(),
+            (5, Some("3610a686".to_owned()))
+        );
+
+        // Sources have to provide exactly the declared length
+        let err = encode(b"hell", 5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = encode(b"hello!", 5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
     #[test]
     fn test_file_handler() {
         // For each entry just returns filenames
@@ -445,9 +1256,12 @@ mod tests {
                 if let Some(s) = self.0.next() {
                     let reader = BufReader::new(s.as_bytes());
                     let fd = FileDescriptor {
-                        path: &Path::new(s),
+                        path: Path::new(s),
+                        name: None,
                         reader,
                         len: s.len() as FilePosition,
+                        metadata: Metadata::default(),
+                        checksum: None,
                     };
                     compress(fd)?;
                     Ok(Some(()))
@@ -467,4 +1281,350 @@ mod tests {
 
         assert_eq!(out, b"DCA\nfoo\n3\nfoo\nbar\n3\nbar\n");
     }
+
+    #[test]
+    fn test_file_list() {
+        let dir = make_dir();
+        dir.child("a").write_str("1").unwrap();
+        dir.child("b c").write_str("2").unwrap();
+        let list = format!(
+            "{}\0\0{}\0",
+            dir.child("a").path().display(),
+            dir.child("b c").path().display()
+        );
+
+        let mut out = Vec::<u8>::new();
+        let mut fhandler = FileListHandler::new(list.as_bytes(), "list", true);
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert_eq!(out, b"DCA\na\n1\n1\nb c\n1\n2\n");
+
+        let lines = list.replace('\0', "\n");
+        let paths: Vec<PathBuf> = FileListHandler::new(lines.as_bytes(), "list", false)
+            .inner
+            .files
+            .collect();
+        assert_eq!(paths, [dir.child("a").path(), dir.child("b c").path()]);
+
+        // Standard input can't provide both the list and a file
+        let mut fhandler = FileListHandler::new("-\n".as_bytes(), STDIN_PATH, false);
+        match compress_into(&mut Vec::new(), &mut fhandler, &mut std_errors()).unwrap_err() {
+            ArchiveError::BadFileIo(path, e) => {
+                assert_eq!(path, Path::new(STDIN_PATH));
+                assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_spool() {
+        let read = |(source, len): (Source, FilePosition)| {
+            let mut buf = Vec::new();
+            io::BufReader::new(source).read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.len() as FilePosition, len);
+            buf
+        };
+
+        assert!(matches!(
+            spool(&b"hello"[..], 5).unwrap().0 .0,
+            SourceKind::Memory(_)
+        ));
+        assert_eq!(read(spool(&b"hello"[..], 5).unwrap()), b"hello");
+
+        assert!(matches!(
+            spool(&b"hello"[..], 2).unwrap().0 .0,
+            SourceKind::Spooled(_)
+        ));
+        assert_eq!(read(spool(&b"hello"[..], 2).unwrap()), b"hello");
+    }
+
+    #[test]
+    fn test_compress_files() {
+        let dir = make_dir();
+        dir.child("archive.dca").write_str("old").unwrap();
+        dir.child("file").write_str("data").unwrap();
+
+        // Failure leaves the original file intact
+        let err = compress_files(
+            &[dir.child("file"), dir.child("nonexisting")],
+            dir.child("archive.dca"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ArchiveError::BadFileIo(..)));
+        dir.child("archive.dca").assert("old");
+        assert_eq!(dir_size(&dir), 2);
+
+        compress_files(&[dir.child("file")], dir.child("archive.dca")).unwrap();
+        dir.child("archive.dca")
+            .assert(b"DCA\nfile\n4\ndata\n" as &[u8]);
+        assert_eq!(dir_size(&dir), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = make_dir();
+        dir.child("file").write_str("data").unwrap();
+        let file = File::options().write(true).open(dir.child("file")).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .unwrap();
+        file.set_permissions(fs::Permissions::from_mode(0o640))
+            .unwrap();
+
+        let files = [dir.child("file")];
+        let mut out = Vec::<u8>::new();
+        compress_into(
+            &mut out,
+            &mut DefaultFileHandler::new(&files),
+            &mut std_errors(),
+        )
+        .unwrap();
+        assert_eq!(out, b"DCA\nfile\n4\ndata\n");
+
+        let mut out = Vec::<u8>::new();
+        let mut fhandler =
+            DefaultFileHandler::new(&files).options(EntryOptions::new().metadata(true));
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert_eq!(out, b"DCA2\nfile\nmtime=1700000000\nmode=640\n4\ndata\n");
+    }
+
+    #[test]
+    fn test_checksum() {
+        let dir = make_dir();
+        dir.child("file").write_str("data").unwrap();
+        dir.child("empty").touch().unwrap();
+
+        let files = [dir.child("file"), dir.child("empty")];
+        let mut out = Vec::<u8>::new();
+        let mut fhandler = DefaultFileHandler::new(&files)
+            .options(EntryOptions::new().checksum(Some(Checksum::Crc32)));
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert_eq!(
+            out,
+            b"DCA2\nfile\ncrc32=adf3f363\n4\ndata\nempty\ncrc32=00000000\n0\n\n"
+        );
+        assert_eq!(decompress::verify(&mut &out[..]).unwrap(), 2);
+
+        // Files are hashed upfront and read again, instead of being read whole first
+        let (mut reader, len, _) = open_file(dir.child("file").path()).unwrap();
+        let options = EntryOptions::new().checksum(Some(Checksum::Crc32));
+        let digest = precompute_checksum(&mut reader, len, options).unwrap();
+        assert_eq!(digest.as_deref(), Some("adf3f363"));
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "data");
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = make_dir();
+        dir.child("first").write_str("123").unwrap();
+        dir.child("second").write_str("abcde").unwrap();
+
+        let files = [dir.child("first"), dir.child("second")];
+        let mut out = Vec::<u8>::new();
+        let mut fhandler = DefaultFileHandler::new(&files).options(EntryOptions::new().index(true));
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert!(out.starts_with(b"DCA\nfirst\n3\n123\nsecond\n5\nabcde\n.dca-index\n"));
+
+        let index = crate::index::read_index(&mut io::Cursor::new(&out))
+            .unwrap()
+            .unwrap();
+        let positions: Vec<_> = index
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.offset, entry.len))
+            .collect();
+        assert_eq!(positions, [("first", 4, 3), ("second", 16, 5)]);
+
+        let mut sink = Vec::new();
+        decompress::extract_entry(&mut io::Cursor::new(&out), "second", None, &mut sink).unwrap();
+        assert_eq!(sink, b"abcde");
+
+        // Index isn't extracted
+        let mut found = Vec::new();
+        decompress::decompress_from(
+            &mut &out[..],
+            &mut CallbackFileHandler(|name, _, _| {
+                found.push(name.to_owned());
+                Ok(())
+            }),
+            &decompress::DefaultErrorHandler::new(Path::new("archive.dca")),
+        )
+        .unwrap();
+        assert_eq!(found, ["first", "second"]);
+
+        // User file can't share the name with the index, unless there's none
+        dir.child(INDEX_NAME).write_str("user").unwrap();
+        let files = [dir.child("first"), dir.child(INDEX_NAME)];
+        let mut out = Vec::<u8>::new();
+        let mut fhandler = DefaultFileHandler::new(&files).options(EntryOptions::new().index(true));
+        match compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap_err() {
+            ArchiveError::InvalidDcaFilename(_, DcaFilenameError::Reserved) => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        let mut out = Vec::<u8>::new();
+        let mut fhandler = DefaultFileHandler::new(&files);
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert_eq!(out, b"DCA\nfirst\n3\n123\n.dca-index\n4\nuser\n");
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_compressed_entries() {
+        let dir = make_dir();
+        dir.child("file").write_str("data data data data").unwrap();
+        dir.child("empty").touch().unwrap();
+
+        let mut out = Vec::<u8>::new();
+        let files = [dir.child("file"), dir.child("empty")];
+        let mut fhandler = DefaultFileHandler::new(&files)
+            .options(EntryOptions::new().compression(EntryCodec::Deflate));
+        compress_into(&mut out, &mut fhandler, &mut std_errors()).unwrap();
+        assert!(out.starts_with(b"DCA2\nfile\ncodec=deflate\nsize=19\n"));
+        assert!(out.ends_with(b"\nempty\n0\n\n"));
+
+        let mut found = Vec::new();
+        decompress::decompress_from(
+            &mut &out[..],
+            &mut CallbackFileHandler(|name, len, reader| {
+                let mut buf = String::new();
+                reader
+                    .read_to_string(&mut buf)
+                    .map_err(ArchiveError::ArchiveIo)?;
+                found.push((name.to_owned(), len, buf));
+                Ok(())
+            }),
+            &decompress::DefaultErrorHandler::new(Path::new("archive.dca")),
+        )
+        .unwrap();
+        assert_eq!(
+            found,
+            [
+                ("file".to_owned(), 19, "data data data data".to_owned()),
+                ("empty".to_owned(), 0, String::new()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compressed_archive() {
+        let dir = make_dir();
+        dir.child("file").write_str("data").unwrap();
+
+        compress_files(&[dir.child("file")], dir.child("archive.dca.gz")).unwrap();
+        let compressed = fs::read(dir.child("archive.dca.gz")).unwrap();
+        assert!(compressed.starts_with(b"\x1f\x8b"));
+
+        let ehandler = decompress::DefaultErrorHandler::new(Path::new("archive.dca.gz"));
+        let entries = crate::archive_entries(&mut &compressed[..], &ehandler).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].name.as_str(), entries[0].len), ("file", 4));
+
+        let err = append_files(&[dir.child("file")], dir.child("archive.dca.gz")).unwrap_err();
+        assert!(matches!(err, ArchiveError::ArchiveIo(_)));
+    }
+
+    #[test]
+    fn test_no_clobber() {
+        let dir = make_dir();
+        dir.child("file").write_str("data").unwrap();
+        dir.child("archive.dca").write_str("old").unwrap();
+        let paths = [dir.child("file")];
+
+        let err =
+            compress_files_with(dir.child("archive.dca"), &mut files(&paths), true).unwrap_err();
+        assert!(matches!(err, ArchiveError::FileExists(_)));
+        dir.child("archive.dca").assert("old");
+
+        compress_files_with(dir.child("archive.dca"), &mut files(&paths), false).unwrap();
+        dir.child("archive.dca")
+            .assert(b"DCA\nfile\n4\ndata\n" as &[u8]);
+        assert_eq!(dir_size(&dir), 2);
+    }
+
+    #[test]
+    fn test_append_files() {
+        let dir = make_dir();
+        dir.child("archive.dca")
+            .write_binary(b"DCA\nfirst\n3\n123\n")
+            .unwrap();
+        dir.child("second").write_str("hello").unwrap();
+
+        append_files(&[dir.child("second")], dir.child("archive.dca")).unwrap();
+        dir.child("archive.dca")
+            .assert(b"DCA\nfirst\n3\n123\nsecond\n5\nhello\n" as &[u8]);
+
+        // Failure keeps original content
+        let err = append_files(
+            &[dir.child("second"), dir.child("nonexisting")],
+            dir.child("archive.dca"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ArchiveError::BadFileIo(..)));
+        dir.child("archive.dca")
+            .assert(b"DCA\nfirst\n3\n123\nsecond\n5\nhello\n" as &[u8]);
+
+        // Malformed archives are left alone
+        dir.child("bad.dca")
+            .write_binary(b"DCA\nfirst\n30\n123\n")
+            .unwrap();
+        let err = append_files(&[dir.child("second")], dir.child("bad.dca")).unwrap_err();
+        assert!(matches!(err, ArchiveError::ArchiveIo(..)));
+        dir.child("bad.dca")
+            .assert(b"DCA\nfirst\n30\n123\n" as &[u8]);
+
+        // Entries with attributes need archive in extended format
+        let files = [dir.child("second")];
+        let mut fhandler = DefaultFileHandler::new(&files)
+            .options(EntryOptions::new().checksum(Some(Checksum::Crc32)));
+        let mut out = Vec::<u8>::new();
+        match append_into(&mut out, &mut fhandler, &mut std_errors(), false).unwrap_err() {
+            ArchiveError::ArchiveIo(e) if e.kind() == io::ErrorKind::Unsupported => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        assert!(out.is_empty());
+        append_into(&mut out, &mut fhandler, &mut std_errors(), true).unwrap();
+        assert_eq!(out, b"second\ncrc32=3610a686\n5\nhello\n");
+    }
+
+    #[test]
+    fn test_append_indexed() {
+        let dir = make_dir();
+        dir.child("first").write_str("123").unwrap();
+        dir.child("second").write_str("hello").unwrap();
+        let archive = dir.child("archive.dca");
+        let files = [dir.child("first")];
+        let mut fhandler = DefaultFileHandler::new(&files).options(EntryOptions::new().index(true));
+        compress_files_with(&archive, &mut fhandler, false).unwrap();
+        let original = fs::read(&archive).unwrap();
+
+        // Failure puts the index back
+        append_files(&[dir.child("nonexisting")], &archive).unwrap_err();
+        assert_eq!(fs::read(&archive).unwrap(), original);
+
+        // New entries take place of the index, which is then written again
+        append_files(&[dir.child("second")], &archive).unwrap();
+        let content = fs::read(&archive).unwrap();
+        assert!(content.starts_with(b"DCA\nfirst\n3\n123\nsecond\n5\nhello\n.dca-index\n"));
+        let index = read_index(&mut io::Cursor::new(content)).unwrap().unwrap();
+        assert_eq!(
+            index,
+            vec![
+                IndexEntry {
+                    name: "first".into(),
+                    offset: 4,
+                    len: 3
+                },
+                IndexEntry {
+                    name: "second".into(),
+                    offset: 16,
+                    len: 5
+                },
+            ]
+        );
+    }
 }
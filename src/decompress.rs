@@ -1,13 +1,22 @@
 //! Handles extraction from DCA archives
 
 use std::cmp::min;
+use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs::{self, File};
-use std::io::{self, prelude::*, BufRead, Seek, SeekFrom};
-use std::path::{Path, PathBuf};
+use std::io::{self, prelude::*, BufRead, Seek};
+use std::path::{Component, Path, PathBuf};
 
+use crate::checksum::{self, Checksum};
+use crate::codec::{Codec, EntryCodec};
 use crate::error::{
-    error, warn, ArchiveError, DecompressionError, FilePosition, Handler as ErrorHandler, Result,
+    error, into_dca_filename, into_dca_path, warn, ArchiveError, DecompressionError, FilePosition,
+    Handler as ErrorHandler, Result,
 };
+use crate::index::{is_locator_of, locate_index, read_index, INDEX_NAME};
+use crate::metadata::Metadata;
+use crate::reader::{attribute, original_len, ArchiveReader, Entry};
+use crate::tempfile::TempFile;
 
 use ArchiveError as E;
 
@@ -37,6 +46,24 @@ impl<'a> DefaultErrorHandler<'a> {
                     position, section
                 );
             }
+            E::UnsafeEntryName(name) => {
+                error!(
+                    "Archive {:?} contains entry {:?} that could be extracted outside of target directory",
+                    self.archive_name, name
+                );
+            }
+            E::FileExists(path) => {
+                error!(
+                    "Extraction of archive {:?} stopped, file {:?} already exists",
+                    self.archive_name, path
+                );
+            }
+            E::ChecksumMismatch(path) => {
+                error!(
+                    "Extraction of archive {:?} stopped, content of {:?} doesn't match its checksum",
+                    self.archive_name, path
+                );
+            }
             _ => {
                 error!(
                     "Extraction of archive {:?} failed due to error {:?}",
@@ -57,75 +84,28 @@ impl<'a> ErrorHandler for DefaultErrorHandler<'a> {
                 );
                 Ok(())
             }
+            E::FileSkipped(fname) => {
+                warn!("File {:?} already exists, skipping.", fname);
+                Ok(())
+            }
+            E::ChecksumMismatch(fname) => {
+                error!(
+                    "Content of file {:?} doesn't match its checksum, skipping.",
+                    fname
+                );
+                Ok(())
+            }
             // Other problems are fatal
             err => Err(err),
         }
     }
 }
 
-/// Returns true if read from the reader matches fixed byte sequence
-fn read_matches<const N: usize>(
-    reader: &mut impl Read,
-    reference: &'static [u8; N],
-    position: &mut FilePosition,
-) -> Result<bool, io::Error> {
-    let mut buf = [0u8; N];
-    reader.read_exact(&mut buf)?;
-
-    let res = &buf == reference;
-    if res {
-        *position += N as FilePosition;
-    }
-    Ok(res)
-}
-
-/// Reads UTF-8 text from the archive up to the following newline (\n) character, and transforms
-/// the read string into appropriate result type via `processor` callback.
-///
-/// Also allows to read nothing, returning None in that case.
-fn read_line<T>(
-    reader: &mut impl BufRead,
-    line_buf: &mut String,
-    position: &mut FilePosition,
-    processor: impl FnOnce(&str) -> Result<T>,
-) -> Result<Option<T>> {
-    line_buf.truncate(0);
-    reader.read_line(line_buf).map_err(E::ArchiveIo)?;
-    if line_buf.is_empty() {
-        return Ok(None);
-    }
-    let res = processor(&line_buf[..line_buf.len() - 1]);
-    if res.is_ok() {
-        *position += line_buf.len() as FilePosition;
-    }
-    res.map(Some)
-}
-
-/// Reads file size segment from the archive
-fn read_file_size(
-    reader: &mut impl BufRead,
-    line_buf: &mut String,
-    position: &mut FilePosition,
-) -> Result<FilePosition> {
-    let old_pos = *position;
-    let handler = |s: &str| {
-        s.parse::<FilePosition>().map_err(|_| E::CorruptedArchive {
-            position: old_pos,
-            section: DecompressionError::FileSize,
-        })
-    };
-    read_line(reader, line_buf, position, handler).and_then(|val| match val {
-        Some(size) => Ok(size),
-        None => Err(E::CorruptedArchive {
-            position: *position,
-            section: DecompressionError::FileSize,
-        }),
-    })
-}
-
 /// Reads contents of the file from the archive into provided sink
 ///
-/// `count` is number of bytes file should have
+/// `count` is number of bytes file should have. If `checksum` with its expected value is given,
+/// content is checked against it once it's written, failing with [`ArchiveError::ChecksumMismatch`].
+/// Checksum that can't be computed with enabled features is left unchecked with a warning.
 ///
 /// Note that if function fails to write into provided sink, remaining byte count
 /// is returned, allow skipping the rest of the file
@@ -134,7 +114,24 @@ fn extract_file(
     count: FilePosition,
     sink: &mut impl Write,
     sink_name: &Path,
+    checksum: Option<(Checksum, &str)>,
 ) -> Result<()> {
+    let mut hasher = match checksum {
+        Some((checksum, _)) if !checksum.is_supported() => {
+            warn!(
+                "Content of file {:?} can't be checked, support of {} checksums is not enabled",
+                sink_name,
+                checksum.name()
+            );
+            None
+        }
+        Some((checksum, _)) => Some(
+            checksum
+                .hasher()
+                .map_err(|e| E::BadFileIo(sink_name.to_owned(), e))?,
+        ),
+        None => None,
+    };
     // Implementation note: the casts between usize and FilePosition seem unifiable, but aren't
     // Buffer is usize(d), which in theoretical case may be much smaller than size of DCA entries
     let mut remaining_size = count;
@@ -149,10 +146,18 @@ fn extract_file(
         }
         sink.write_all(&buf[..read_upto])
             .map_err(|e| E::BadFileIo(sink_name.to_owned(), e))?;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&buf[..read_upto]);
+        }
         reader.consume(read_upto);
         remaining_size -= read_upto as FilePosition;
     }
-    Ok(())
+    match (hasher.map(checksum::Hasher::finish), checksum) {
+        (Some(actual), Some((_, expected))) if !actual.eq_ignore_ascii_case(expected) => {
+            Err(E::ChecksumMismatch(sink_name.to_owned()))
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Metadata about file about to be extracted
@@ -161,8 +166,14 @@ pub struct FileDescriptor<'a, R: BufRead> {
     pub name: &'a str,
     /// File size of the file entry in bytes
     pub len: FilePosition,
+    /// Size of the payload as stored in the archive, differs from [`Self::len`] for compressed entries
+    pub stored_len: FilePosition,
     /// From this reader, you can read up to [`Self::len`] bytes. Attempt to reading more than that is, however, well defined and results in EOF
     pub reader: &'a mut R,
+    /// Modification time, permissions and such, if stored in the archive of extended format
+    pub metadata: &'a Metadata,
+    /// Checksum of the content along with its expected value, if stored in the archive, see [`crate::checksum`]
+    pub checksum: Option<(Checksum, &'a str)>,
 }
 
 /// Represents callback for consuming files extracted with [`decompress_from`].
@@ -174,18 +185,254 @@ pub trait FileHandler {
     /// or due to internal errors (use [`ArchiveError::BadFileIo`]).
     ///
     /// Usage of `BadFileIo` indicates that further exctraction from archive is still possible.
+    /// Handler may also refuse the entry due to its name with [`ArchiveError::UnsafeEntryName`]
+    /// or due to existing file with [`ArchiveError::FileExists`] and [`ArchiveError::FileSkipped`].
+    /// All of these are passed into [`ErrorHandler`].
     /// Note that final position in reader is irrelevant.
     fn on_file<R: BufRead>(&mut self, file: FileDescriptor<'_, R>) -> Result<()>;
 }
 
+/// Returns true if entry name can be used as a filename without escaping the target directory
+///
+/// Besides DCA naming requirements, rejects empty names, `.`, `..`, NUL characters and anything
+/// the platform considers path separator or prefix.
+pub fn is_safe_name(name: &str) -> bool {
+    into_dca_filename(OsStr::new(name)).is_ok()
+        && !name.contains('\0')
+        && !name.chars().any(std::path::is_separator)
+        && matches!(
+            Path::new(name).components().collect::<Vec<_>>()[..],
+            [Component::Normal(_)]
+        )
+}
+
+/// Transforms entry name into one passing [`is_safe_name`] by replacing offending characters with `_`
+pub fn sanitize_name(name: &str) -> String {
+    let unsafe_char = |ch: char| {
+        ch == '\0' || ch == '\n' || std::path::is_separator(ch) || (cfg!(windows) && ch == ':')
+    };
+    let sanitized: String = name
+        .chars()
+        .map(|ch| if unsafe_char(ch) { '_' } else { ch })
+        .collect();
+    match &sanitized[..] {
+        "" => "_".to_owned(),
+        "." | ".." => sanitized.replace('.', "_"),
+        _ => sanitized,
+    }
+}
+
+/// Variant of [`is_safe_name`] for archives using paths extension
+///
+/// Name has to pass [`into_dca_path`] and each of its components has to pass [`is_safe_name`].
+pub fn is_safe_path(name: &str) -> bool {
+    into_dca_path(OsStr::new(name)).is_ok() && name.split('/').all(is_safe_name)
+}
+
+/// Transforms entry name into one passing [`is_safe_path`] by dropping empty components and
+/// sanitizing the rest with [`sanitize_name`]
+pub fn sanitize_path(name: &str) -> String {
+    let components: Vec<String> = name
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(sanitize_name)
+        .collect();
+    if components.is_empty() {
+        "_".to_owned()
+    } else {
+        components.join("/")
+    }
+}
+
+/// Treatment of entries whose names aren't safe to extract, see [`is_safe_name`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnsafeNamePolicy {
+    /// Entry is reported as [`ArchiveError::UnsafeEntryName`]
+    #[default]
+    Reject,
+    /// Entry is extracted under name transformed by [`sanitize_name`]
+    Sanitize,
+    /// Entry is skipped with a warning
+    Skip,
+}
+
+/// Treatment of files that already exist in the target directory
+///
+/// Except for [`Self::Overwrite`], files are never replaced unless the policy says so,
+/// even if they're created by someone else while the entry is being extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OverwritePolicy {
+    /// Existing file is replaced
+    #[default]
+    Overwrite,
+    /// Existing file is kept, extraction is reported as [`ArchiveError::FileSkipped`]
+    Skip,
+    /// Existing file is replaced only if the entry is larger, as the smaller one is considered an older version.
+    /// Otherwise it's kept and reported as [`ArchiveError::FileSkipped`]
+    KeepLarger,
+    /// Extracted file gets numbered suffix, see [`numbered_name`]
+    Rename,
+    /// Existing file is kept and reported as [`ArchiveError::FileExists`]
+    Fail,
+}
+
+/// Treatment of entries sharing the same name
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DuplicatePolicy {
+    /// Only the first entry that extracts successfully is kept, the others are skipped with a warning
+    KeepFirst,
+    /// Each entry replaces the previously extracted one
+    #[default]
+    KeepLast,
+    /// Subsequent entries are extracted under numbered names, see [`numbered_name`]
+    Number,
+}
+
+/// Creates variant of `name` with number `n` inserted before its extension, for example `notes (2).txt`
+///
+/// If name is a path, only its last component is changed.
+pub fn numbered_name(name: &str, n: usize) -> String {
+    let base = name.rfind('/').map_or(0, |slash| slash + 1);
+    match name[base..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = base + dot;
+            format!("{} ({}){}", &name[..dot], n, &name[dot..])
+        }
+        _ => format!("{} ({})", name, n),
+    }
+}
+
 /// Standard [`FileHandler`] implemented by extracting all files into given directory
+///
+/// Entry names are checked with [`is_safe_name`] and unsafe ones are treated according to [`UnsafeNamePolicy`],
+/// by default they're rejected. Existing files are treated according to [`OverwritePolicy`], by default they're overwritten.
+/// Entries sharing the same name are treated according to [`DuplicatePolicy`], by default the last one is kept.
+///
+/// Archives using paths extension (see [`into_dca_path`]) can be extracted with [`Self::paths`] enabled, in which case
+/// names are checked with [`is_safe_path`] and intermediate directories are created as needed.
+///
+/// Each file is written into a temporary file in the work directory first and renamed to its final name only
+/// after it's complete, so interrupted extraction doesn't leave partially written files behind
+/// (except for hidden temporary files if the process is killed).
+///
+/// Modification times stored in the archive are restored, unless disabled by [`Self::metadata`].
+/// Permissions are restored only if enabled by [`Self::permissions`].
 pub struct DefaultFileHandler<'a> {
     work_directory: &'a Path,
+    unsafe_names: UnsafeNamePolicy,
+    overwrite: OverwritePolicy,
+    duplicates: DuplicatePolicy,
+    fsync: bool,
+    paths: bool,
+    metadata: bool,
+    permissions: bool,
+    /// Number of occurrences of each entry name
+    seen: HashMap<String, usize>,
+    /// Where each entry name was successfully extracted
+    extracted: HashMap<String, PathBuf>,
 }
 impl<'a> DefaultFileHandler<'a> {
     /// Constructor. `work_directory` is a place where archive's content should be extracted into
     pub fn new(work_directory: &'a Path) -> Self {
-        Self { work_directory }
+        Self {
+            work_directory,
+            unsafe_names: UnsafeNamePolicy::default(),
+            overwrite: OverwritePolicy::default(),
+            duplicates: DuplicatePolicy::default(),
+            fsync: false,
+            paths: false,
+            metadata: true,
+            permissions: false,
+            seen: HashMap::new(),
+            extracted: HashMap::new(),
+        }
+    }
+    /// Sets treatment of entries sharing the same name
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+    /// Sets treatment of files already present in the work directory
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+    /// Chooses path for the entry according to the overwrite policy, along with whether it may replace
+    /// existing file. Otherwise the file is created only if nothing exists there at the time of its creation.
+    fn target_path(&self, fname: &str, len: FilePosition) -> Result<(PathBuf, bool)> {
+        let path = self.work_directory.join(fname);
+        let exists = |path: &Path| fs::symlink_metadata(path).is_ok();
+        match self.overwrite {
+            OverwritePolicy::Overwrite => Ok((path, true)),
+            OverwritePolicy::Skip if exists(&path) => Err(E::FileSkipped(path)),
+            OverwritePolicy::Fail if exists(&path) => Err(E::FileExists(path)),
+            OverwritePolicy::Skip | OverwritePolicy::Fail => Ok((path, false)),
+            OverwritePolicy::KeepLarger => match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.len() >= len => Err(E::FileSkipped(path)),
+                Ok(_) => Ok((path, true)),
+                Err(_) => Ok((path, false)),
+            },
+            OverwritePolicy::Rename => {
+                if !exists(&path) {
+                    return Ok((path, false));
+                }
+                let path = (2..)
+                    .map(|n| self.work_directory.join(numbered_name(fname, n)))
+                    .find(|path| !exists(path))
+                    .expect("unbounded range");
+                warn!("File {:?} already exists, extracting as {:?}", fname, path);
+                Ok((path, false))
+            }
+        }
+    }
+    /// Sets whether entry names are treated as relative paths, see [`into_dca_path`]
+    pub fn paths(mut self, paths: bool) -> Self {
+        self.paths = paths;
+        self
+    }
+    /// Creates directories leading to entry `fname` of an archive using paths extension
+    ///
+    /// Existing symbolic links aren't followed, as they could lead outside of the work directory.
+    fn create_parents(&self, fname: &str) -> Result<()> {
+        let mut dir = self.work_directory.to_owned();
+        let mut components: Vec<&str> = fname.split('/').collect();
+        components.pop();
+        for component in components {
+            dir.push(component);
+            match fs::symlink_metadata(&dir) {
+                Ok(meta) if meta.is_dir() => (),
+                Ok(meta) if meta.file_type().is_symlink() => {
+                    return Err(E::UnsafeEntryName(fname.to_owned()))
+                }
+                Ok(_) => return Err(E::BadFileIo(dir, io::ErrorKind::AlreadyExists.into())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    fs::create_dir(&dir).map_err(|e| E::BadFileIo(dir.clone(), e))?
+                }
+                Err(e) => return Err(E::BadFileIo(dir, e)),
+            }
+        }
+        Ok(())
+    }
+    /// Sets whether extracted files are synced to disk before being moved into place
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+    /// Sets whether stored modification times are restored on extracted files, see [`Metadata::apply`]
+    pub fn metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+    /// Sets whether stored permissions are restored too, unless metadata aren't restored at all.
+    /// Off by default, as they'd override the umask, possibly exposing extracted files to other users
+    pub fn permissions(mut self, permissions: bool) -> Self {
+        self.permissions = permissions;
+        self
+    }
+    /// Sets treatment of entries with names unsafe for extraction
+    pub fn unsafe_names(mut self, policy: UnsafeNamePolicy) -> Self {
+        self.unsafe_names = policy;
+        self
     }
 }
 
@@ -195,26 +442,96 @@ impl<'a> FileHandler for DefaultFileHandler<'a> {
             name: fname,
             reader,
             len,
+            metadata,
+            checksum,
+            ..
         } = file;
-        let fname_buf: PathBuf = self.work_directory.join(&fname);
-
-        let bad_io = |e| E::BadFileIo(fname_buf.clone(), e);
-        let file = File::create(&fname_buf).map_err(bad_io)?;
+        let is_safe = if self.paths {
+            is_safe_path(fname)
+        } else {
+            is_safe_name(fname)
+        };
+        let sanitized;
+        let fname = if is_safe {
+            fname
+        } else {
+            match self.unsafe_names {
+                UnsafeNamePolicy::Reject => return Err(E::UnsafeEntryName(fname.to_owned())),
+                UnsafeNamePolicy::Sanitize => {
+                    sanitized = if self.paths {
+                        sanitize_path(fname)
+                    } else {
+                        sanitize_name(fname)
+                    };
+                    warn!("Entry {:?} is extracted as {:?}", fname, sanitized);
+                    &sanitized
+                }
+                UnsafeNamePolicy::Skip => {
+                    warn!("Entry {:?} has unsafe name, skipping.", fname);
+                    return Ok(());
+                }
+            }
+        };
+        let count = self.seen.entry(fname.to_owned()).or_insert(0);
+        *count += 1;
+        let count = *count;
+        let (target_name, extracted) = match (self.duplicates, self.extracted.get(fname)) {
+            (_, None) if count == 1 => (fname.to_owned(), None),
+            // Only successfully extracted entry counts, so that failed one is taken over by the next
+            (DuplicatePolicy::KeepFirst, None) => (fname.to_owned(), None),
+            (DuplicatePolicy::KeepFirst, Some(_)) => {
+                warn!("Entry {:?} is duplicate, keeping the first one.", fname);
+                return Ok(());
+            }
+            (DuplicatePolicy::KeepLast, extracted) => (fname.to_owned(), extracted.cloned()),
+            (DuplicatePolicy::Number, _) => (numbered_name(fname, count), None),
+        };
+        let (mut fname_buf, replace) = match extracted {
+            // Replacing our own extracted file isn't subject to overwrite policy
+            Some(path) => (path, true),
+            None => self.target_path(&target_name, len)?,
+        };
 
-        let write_file = || {
-            let mut writer = io::BufWriter::new(file);
+        if self.paths {
+            self.create_parents(fname)?;
+        }
 
-            extract_file(reader, len, &mut writer, &fname_buf)
-        };
-        match write_file() {
-            Ok(()) => Ok(()),
-            Err(err) => {
-                if let Err(del_err) = fs::remove_file(&fname_buf) {
-                    error!("Extraction of {:?} failed, but the temporary file couldn't be deleted due to error {}. Please remove it manually.", fname_buf, del_err);
+        // File is written under temporary name, so that interrupted extraction never leaves incomplete file
+        // under its final name. Temporary file is deleted on failure.
+        let bad_io = |e| E::BadFileIo(fname_buf.clone(), e);
+        let mut temp = TempFile::new_sibling(&fname_buf).map_err(bad_io)?;
+        let mut writer = io::BufWriter::new(temp.file());
+        extract_file(reader, len, &mut writer, &fname_buf, checksum)?;
+        writer.flush().map_err(bad_io)?;
+        drop(writer);
+        if self.metadata {
+            let mut metadata = metadata.clone();
+            if !self.permissions {
+                metadata.mode = None;
+            }
+            metadata.apply(temp.file()).map_err(bad_io)?;
+        }
+        if self.fsync {
+            temp.file().sync_all().map_err(bad_io)?;
+        }
+        if replace {
+            temp.persist(&fname_buf).map_err(bad_io)?;
+        } else {
+            // File may have been created since the target was chosen
+            while let Err(e) = temp.persist_new(&fname_buf) {
+                if e.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(E::BadFileIo(fname_buf, e));
+                }
+                match self.overwrite {
+                    OverwritePolicy::Rename => fname_buf = self.target_path(&target_name, len)?.0,
+                    OverwritePolicy::Fail => return Err(E::FileExists(fname_buf)),
+                    _ => return Err(E::FileSkipped(fname_buf)),
                 }
-                Err(err)
             }
         }
+
+        self.extracted.insert(fname.to_owned(), fname_buf);
+        Ok(())
     }
 }
 
@@ -232,6 +549,57 @@ where
     }
 }
 
+/// [`FileHandler`] wrapper that passes only entries accepted by `filter` into the inner handler
+///
+/// Rejected entries are not read at all, so seekable archives skip them without reading their payload.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::decompress::{decompress_files_with, DefaultFileHandler, FilterFileHandler};
+/// use dca::select::Selection;
+/// use std::path::Path;
+///
+/// let selection = Selection::new().glob("report-*.csv");
+/// let mut fhandler = FilterFileHandler::new(DefaultFileHandler::new(Path::new("output")), |name| {
+///     selection.matches(name)
+/// });
+/// decompress_files_with("archive.dca", &mut fhandler).expect("decompression failed");
+/// ```
+pub struct FilterFileHandler<H, F> {
+    inner: H,
+    filter: F,
+}
+
+impl<H, F> FilterFileHandler<H, F>
+where
+    H: FileHandler,
+    F: FnMut(&str) -> bool,
+{
+    /// Constructor. `filter` returns true for names of entries that should be processed
+    pub fn new(inner: H, filter: F) -> Self {
+        Self { inner, filter }
+    }
+    /// Unwraps the inner handler
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H, F> FileHandler for FilterFileHandler<H, F>
+where
+    H: FileHandler,
+    F: FnMut(&str) -> bool,
+{
+    fn on_file<R: BufRead>(&mut self, file: FileDescriptor<'_, R>) -> Result<()> {
+        if (self.filter)(file.name) {
+            self.inner.on_file(file)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 // Note: simpler wrapper-less version of CallbackFileHandler that compiler rejects on use
 // impl<C> FileHandler for C
 // where
@@ -250,81 +618,561 @@ where
 ///
 /// Also allows detailed custom handling of various errors through [`ErrorHandler`],
 /// notably including ignoring failed extractions outright.
-pub fn decompress_from(
-    reader: &mut (impl BufRead + Seek),
+///
+/// Reader is processed strictly forward, so pipes, sockets or standard input can be used directly.
+/// Parts of payloads left unread by the handler are read and discarded. Reported positions are
+/// relative to the point where the reader started.
+///
+/// If the archive is seekable, [`decompress_from_seekable`] skips unread payloads more efficiently.
+///
+/// Archives wrapped in compressed stream are detected and decompressed, see [`crate::codec`].
+/// Positions are then relative to the decompressed stream.
+pub fn decompress_from<R: BufRead>(
+    reader: &mut R,
     handle_file: &mut impl FileHandler,
     handle_err: &impl ErrorHandler,
 ) -> Result<()> {
-    let mut position = reader.stream_position().map_err(E::ArchiveIo)?;
+    match Codec::detect(reader).map_err(E::ArchiveIo)? {
+        Codec::None => decompress_entries(
+            ArchiveReader::new(reader),
+            IndexHint::Unknown,
+            handle_file,
+            handle_err,
+        ),
+        codec => decompress_entries(
+            ArchiveReader::new(codec.decoder(reader).map_err(E::ArchiveIo)?),
+            IndexHint::Unknown,
+            handle_file,
+            handle_err,
+        ),
+    }
+}
 
-    // Header
-    if !read_matches(reader, b"DCA\n", &mut position).map_err(E::ArchiveIo)? {
-        return Err(E::CorruptedArchive {
-            position,
-            section: DecompressionError::Header,
+/// Variant of [`decompress_from`] that skips parts of payloads unread by the handler by seeking.
+///
+/// Reported positions are absolute positions in the reader. Compressed streams can't be seeked,
+/// so they're read forward as in [`decompress_from`].
+pub fn decompress_from_seekable<R: BufRead + Seek>(
+    reader: &mut R,
+    handle_file: &mut impl FileHandler,
+    handle_err: &impl ErrorHandler,
+) -> Result<()> {
+    if Codec::detect(reader).map_err(E::ArchiveIo)? != Codec::None {
+        return decompress_from(reader, handle_file, handle_err);
+    }
+    let start = reader.stream_position().map_err(E::ArchiveIo)?;
+    // Malformed archives are reported by the decompression itself
+    let index = locate_index(reader)
+        .unwrap_or(None)
+        .map(|offset| start + offset);
+    reader
+        .seek(io::SeekFrom::Start(start))
+        .map_err(E::ArchiveIo)?;
+    decompress_entries(
+        ArchiveReader::new_seekable(reader)?,
+        IndexHint::Located(index),
+        handle_file,
+        handle_err,
+    )
+}
+
+/// Reader of entry that can't be decoded, failing with the original error once it's actually read
+struct Undecodable(io::Error);
+
+impl Read for Undecodable {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.0.kind(), self.0.to_string()))
+    }
+}
+
+impl BufRead for Undecodable {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Err(io::Error::new(self.0.kind(), self.0.to_string()))
+    }
+    fn consume(&mut self, _amt: usize) {}
+}
+
+/// Reader of stored payload, noting whether the archive failed to provide all of it
+struct PayloadReader<R> {
+    inner: R,
+    remaining: FilePosition,
+    failed: bool,
+}
+
+impl<R: BufRead> Read for PayloadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        match result {
+            Ok(0) if !buf.is_empty() && self.remaining > 0 => self.failed = true,
+            Ok(read) => self.remaining = self.remaining.saturating_sub(read as FilePosition),
+            Err(_) => self.failed = true,
+        }
+        result
+    }
+}
+
+impl<R: BufRead> BufRead for PayloadReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let result = self.inner.fill_buf();
+        match &result {
+            Ok(buf) if buf.is_empty() && self.remaining > 0 => self.failed = true,
+            Ok(_) => (),
+            Err(_) => self.failed = true,
+        }
+        result
+    }
+    fn consume(&mut self, amt: usize) {
+        self.remaining = self.remaining.saturating_sub(amt as FilePosition);
+        self.inner.consume(amt);
+    }
+}
+
+/// Turns failure of the handler to read payload that the archive provided whole into error of the entry,
+/// as it's caused by the payload's encoding
+fn decoding_result(result: Result<()>, name: &str, archive_failed: bool) -> Result<()> {
+    match result {
+        Err(E::ArchiveIo(e)) if !archive_failed => Err(E::BadFileIo(PathBuf::from(name), e)),
+        result => result,
+    }
+}
+
+/// Passes entry with payload of `stored_len` bytes in `reader` to the handler, decoding compressed entries.
+///
+/// Entries with unknown or unsupported codec fail only when read, so that handlers not interested in
+/// their content (such as listing) can process them. Failures of decoding such entries are reported as
+/// [`ArchiveError::BadFileIo`] of the entry name, unlike failures of reading the archive itself.
+pub(crate) fn on_entry<R: BufRead>(
+    handle_file: &mut impl FileHandler,
+    name: &str,
+    stored_len: FilePosition,
+    attributes: &[(String, String)],
+    reader: &mut R,
+) -> Result<()> {
+    let len = original_len(attributes, stored_len);
+    let metadata = &Metadata::from_attributes(attributes);
+    let checksum = checksum::expected(attributes);
+    let codec =
+        match attribute(attributes, "codec").map(|codec| (codec, EntryCodec::from_name(codec))) {
+            None => EntryCodec::Stored,
+            Some((_, Some(codec))) => codec,
+            Some((codec, None)) => {
+                let err = io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unknown codec {:?}", codec),
+                );
+                let result = handle_file.on_file(FileDescriptor {
+                    name,
+                    len,
+                    stored_len,
+                    reader: &mut Undecodable(err),
+                    metadata,
+                    checksum,
+                });
+                return decoding_result(result, name, false);
+            }
+        };
+    if codec == EntryCodec::Stored {
+        return handle_file.on_file(FileDescriptor {
+            name,
+            len,
+            stored_len,
+            reader,
+            metadata,
+            checksum,
         });
     }
+    let mut payload = PayloadReader {
+        inner: reader,
+        remaining: stored_len,
+        failed: false,
+    };
+    let mut decoder = codec
+        .decoder(&mut payload)
+        .unwrap_or_else(|e| Box::new(Undecodable(e)));
+    let result = handle_file.on_file(FileDescriptor {
+        name,
+        len,
+        stored_len,
+        reader: &mut decoder,
+        metadata,
+        checksum,
+    });
+    drop(decoder);
+    decoding_result(result, name, payload.failed)
+}
 
-    let mut line_buf = String::new();
+/// Passes error of the handler processing entry to the error handler, if it's not fatal.
+/// `position` is the current position in the archive.
+fn handle_result(
+    result: Result<()>,
+    position: FilePosition,
+    handle_err: &impl ErrorHandler,
+) -> Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => match e {
+            E::ArchiveIo(io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(E::CorruptedArchive {
+                    position,
+                    section: DecompressionError::Payload,
+                })
+            }
+            E::BadFileIo(..)
+            | E::UnsafeEntryName(..)
+            | E::FileExists(..)
+            | E::FileSkipped(..)
+            | E::ChecksumMismatch(..) => handle_err.on_err(e),
+            E::ArchiveIo(..) => Err(e),
+            _ => {
+                warn!(
+                    "FileHandler of decompress_from returned unexpected error type {:?}",
+                    e
+                );
+                Err(e)
+            }
+        },
+    }
+}
+
+/// Largest entry named [`INDEX_NAME`] held in memory by readers that can't find the index upfront.
+/// Larger entries are treated as any other file
+const MAX_HELD_INDEX: FilePosition = 1 << 24;
+
+/// What's known about the index entry (see [`crate::index`]) of the archive being read
+enum IndexHint {
+    /// Position of the index entry, if the archive has one
+    Located(Option<FilePosition>),
+    /// Archive can't be seeked, so the index is recognized only once it turns out to be the last entry
+    Unknown,
+}
+
+/// Entry named [`INDEX_NAME`] along with its payload, held back until it's known whether it's the index
+struct HeldEntry {
+    start: FilePosition,
+    name: String,
+    attributes: Vec<(String, String)>,
+    payload: Vec<u8>,
+}
+
+/// Common implementation of [`decompress_from`] variants, feeding entries of the archive into the handler
+///
+/// Index entry (see [`crate::index`]) is skipped. Unless its position is known, it's recognized only as the last entry,
+/// so entries named [`INDEX_NAME`] up to [`MAX_HELD_INDEX`] bytes are read into memory first and passed to the handler
+/// once another entry follows.
+fn decompress_entries<R: BufRead>(
+    mut archive: ArchiveReader<R>,
+    index: IndexHint,
+    handle_file: &mut impl FileHandler,
+    handle_err: &impl ErrorHandler,
+) -> Result<()> {
+    let start = archive.position();
+    let mut held: Option<HeldEntry> = None;
+    let mut entries = archive.entries();
     loop {
-        let fname: String =
-            match read_line(reader, &mut line_buf, &mut position, |s| Ok(s.to_owned()))? {
-                // Final file
-                None => break,
-                Some(fname) => fname,
-            };
-
-        let fsize = read_file_size(reader, &mut line_buf, &mut position)?;
-
-        let footer_position = position + fsize;
-
-        match handle_file.on_file(FileDescriptor {
-            name: &fname,
-            len: fsize,
-            reader: &mut reader.take(fsize as FilePosition),
-        }) {
-            Ok(()) => (),
-            Err(e) => match e {
-                E::ArchiveIo(io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof => {
-                    // Shouldn't fail
-                    let position = reader.seek(SeekFrom::End(0)).unwrap_or(FilePosition::MAX);
-                    return Err(E::CorruptedArchive {
-                        position,
-                        section: DecompressionError::Payload,
-                    });
-                }
-                E::BadFileIo(..) => handle_err.on_err(e)?,
-                E::ArchiveIo(..) => return Err(e),
-                _ => {
-                    warn!(
-                        "FileHandler of decompress_from returned unexpected error type {:?}",
-                        e
-                    );
-                    return Err(e);
-                }
-            },
+        let entry = entries.next();
+        if let Some(held) = held.take() {
+            if entry.is_some() || !is_locator_of(&held.payload, held.start - start) {
+                let len = held.payload.len() as FilePosition;
+                let result = on_entry(
+                    handle_file,
+                    &held.name,
+                    len,
+                    &held.attributes,
+                    &mut &held.payload[..],
+                );
+                handle_result(result, held.start, handle_err)?;
+            }
         }
-        // This is mildly redundant if handler is well behaved and already fully reads up to this point,
-        // but we can't depend on soundness of external code + it streamlines the handler contract
-        reader
-            .seek(SeekFrom::Start(footer_position))
-            .map_err(E::ArchiveIo)?;
-        position = footer_position;
-        // Footer
-        if !read_matches(reader, b"\n", &mut position).map_err(E::ArchiveIo)? {
-            return Err(ArchiveError::CorruptedArchive {
-                position,
-                section: DecompressionError::Footer,
+        let mut entry = match entry {
+            Some(entry) => entry?,
+            None => break,
+        };
+        let name = entry.name().to_owned();
+        let len = entry.len();
+        let attributes = entry.attributes().to_vec();
+
+        let hold = match index {
+            IndexHint::Located(position) if position == Some(entry.start()) => continue,
+            IndexHint::Located(_) => false,
+            IndexHint::Unknown => name == INDEX_NAME && len <= MAX_HELD_INDEX,
+        };
+        if hold {
+            let mut payload = Vec::new();
+            entry.read_to_end(&mut payload).map_err(E::ArchiveIo)?;
+            if payload.len() as FilePosition != len {
+                return Err(E::CorruptedArchive {
+                    position: entry.position(),
+                    section: DecompressionError::Payload,
+                });
+            }
+            held = Some(HeldEntry {
+                start: entry.start(),
+                name,
+                attributes,
+                payload,
             });
+            continue;
         }
+        let result = on_entry(handle_file, &name, len, &attributes, &mut entry);
+        handle_result(result, entry.position(), handle_err)?;
     }
 
     Ok(())
 }
 
+/// [`ErrorHandler`] treating every problem as fatal
+struct StrictErrorHandler;
+
+impl ErrorHandler for StrictErrorHandler {
+    fn on_err(&self, err: ArchiveError) -> Result<()> {
+        Err(err)
+    }
+}
+
+/// [`FileHandler`] copying payload of `index`-th entry called `name` into the sink
+struct SinkFileHandler<'a, W> {
+    name: &'a str,
+    index: usize,
+    seen: usize,
+    sink: &'a mut W,
+}
+
+impl<'a, W: Write> FileHandler for SinkFileHandler<'a, W> {
+    fn on_file<R: BufRead>(&mut self, file: FileDescriptor<'_, R>) -> Result<()> {
+        if file.name != self.name {
+            return Ok(());
+        }
+        self.seen += 1;
+        if self.seen - 1 != self.index {
+            return Ok(());
+        }
+        extract_file(
+            file.reader,
+            file.len,
+            self.sink,
+            Path::new(file.name),
+            file.checksum,
+        )
+    }
+}
+
+/// Resolves `index` of entry called `name` among `count` entries of that name, see [`extract_entry`]
+fn choose_index(name: &str, index: Option<usize>, count: usize) -> Result<usize> {
+    let index = match index {
+        None if count > 1 => return Err(E::AmbiguousEntry(name.to_owned(), count)),
+        None => 0,
+        Some(index) => index,
+    };
+    if index >= count {
+        return Err(E::EntryNotFound(name.to_owned()));
+    }
+    Ok(index)
+}
+
+/// Writes payload of single entry called `name` into `sink`, without extracting anything else.
+///
+/// Names of entries don't have to be unique, so if there are multiple entries called `name`,
+/// `index` chooses one of them (counting from zero). Without `index`, such situation fails with
+/// [`ArchiveError::AmbiguousEntry`]. If there's no such entry, [`ArchiveError::EntryNotFound`] is returned.
+///
+/// If the archive has index (see [`crate::index`]), entry is found with it directly.
+/// Otherwise archive is read twice, first pass only finds the entry.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::decompress::extract_entry;
+/// use std::fs::File;
+/// use std::io::{self, BufReader};
+///
+/// let mut archive = BufReader::new(File::open("archive.dca").unwrap());
+/// extract_entry(&mut archive, "notes.txt", None, &mut io::stdout()).expect("extraction failed");
+/// ```
+pub fn extract_entry(
+    reader: &mut (impl BufRead + Seek),
+    name: &str,
+    index: Option<usize>,
+    sink: &mut impl Write,
+) -> Result<()> {
+    let start = reader.stream_position().map_err(E::ArchiveIo)?;
+
+    if let Some(entries) = read_index(reader)? {
+        let offsets: Vec<FilePosition> = entries
+            .iter()
+            .filter(|entry| entry.name == name)
+            .map(|entry| entry.offset)
+            .collect();
+        let offset = offsets[choose_index(name, index, offsets.len())?];
+        reader
+            .seek(io::SeekFrom::Start(start))
+            .map_err(E::ArchiveIo)?;
+        let mut archive = ArchiveReader::new_seekable(&mut *reader)?;
+        archive.seek_entry(start + offset)?;
+        let mut entry = archive
+            .entries()
+            .next()
+            .ok_or_else(|| E::EntryNotFound(name.to_owned()))??;
+        if entry.name() != name {
+            return Err(E::EntryNotFound(name.to_owned()));
+        }
+        let (len, attributes) = (entry.len(), entry.attributes().to_vec());
+        let mut fhandler = SinkFileHandler {
+            name,
+            index: 0,
+            seen: 0,
+            sink,
+        };
+        on_entry(&mut fhandler, name, len, &attributes, &mut entry)?;
+        return fhandler
+            .sink
+            .flush()
+            .map_err(|e| E::BadFileIo(PathBuf::from(name), e));
+    }
+    reader
+        .seek(io::SeekFrom::Start(start))
+        .map_err(E::ArchiveIo)?;
+
+    let mut count = 0;
+    let mut fhandler = CallbackFileHandler(|entry, _len, _reader| {
+        if entry == name {
+            count += 1;
+        }
+        Ok(())
+    });
+    decompress_from_seekable(reader, &mut fhandler, &StrictErrorHandler)?;
+    let index = choose_index(name, index, count)?;
+
+    reader
+        .seek(io::SeekFrom::Start(start))
+        .map_err(E::ArchiveIo)?;
+    let mut fhandler = SinkFileHandler {
+        name,
+        index,
+        seen: 0,
+        sink,
+    };
+    decompress_from_seekable(reader, &mut fhandler, &StrictErrorHandler)?;
+    fhandler
+        .sink
+        .flush()
+        .map_err(|e| E::BadFileIo(PathBuf::from(name), e))
+}
+
+/// Reads decoded content of the entry, checking it against its checksum.
+///
+/// Returns None if there's no checksum to check or the entry can't be decoded with enabled features.
+/// Checksum that can't be computed with enabled features is skipped with a warning.
+fn content_checksum<R: BufRead>(entry: &mut Entry<'_, R>) -> Option<io::Result<bool>> {
+    let (checksum, expected) = checksum::expected(entry.attributes())?;
+    let (checksum, expected) = (checksum, expected.to_owned());
+    if !checksum.is_supported() {
+        warn!(
+            "Content of entry {:?} can't be checked, support of {} checksums is not enabled",
+            entry.name(),
+            checksum.name()
+        );
+        return None;
+    }
+    let codec = match entry.attribute("codec") {
+        None => EntryCodec::Stored,
+        Some(codec) => EntryCodec::from_name(codec)?,
+    };
+    let result: io::Result<bool> = (|| {
+        let mut hasher = checksum.hasher()?;
+        let mut decoder = codec.decoder(&mut *entry)?;
+        loop {
+            let buf = decoder.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            hasher.update(buf);
+            let read = buf.len();
+            decoder.consume(read);
+        }
+        Ok(hasher.finish().eq_ignore_ascii_case(&expected))
+    })();
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => None,
+        result => Some(result),
+    }
+}
+
+/// Checks structure of the whole archive without extracting anything.
+///
+/// Reads the archive with the same parser as [`decompress_from`], including all payloads, so that their
+/// lengths are checked too. First problem is reported as [`ArchiveError::CorruptedArchive`], with position
+/// relative to the point where the reader started, or [`ArchiveError::ArchiveIo`] if the reader itself fails.
+///
+/// Returns number of entries in the archive, not counting the index (see [`crate::index`]) if it's the last entry.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::decompress::verify;
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// let mut archive = BufReader::new(File::open("archive.dca").unwrap());
+/// match verify(&mut archive) {
+///     Ok(count) => println!("Archive with {} entries is valid.", count),
+///     Err(err) => println!("Archive is broken: {}", err),
+/// }
+/// ```
+pub fn verify(reader: &mut impl BufRead) -> Result<usize> {
+    let mut archive = ArchiveReader::new(reader);
+    let mut count = 0;
+    let mut last_is_index = false;
+    let mut result = Ok(());
+    for entry in archive.entries() {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        };
+        let checked = content_checksum(&mut entry);
+        last_is_index = false;
+        if entry.name() == INDEX_NAME && entry.len() <= MAX_HELD_INDEX {
+            let mut payload = Vec::new();
+            entry.read_to_end(&mut payload).map_err(E::ArchiveIo)?;
+            last_is_index = is_locator_of(&payload, entry.start());
+        }
+        io::copy(&mut entry, &mut io::sink()).map_err(E::ArchiveIo)?;
+        if entry.position() - entry.offset() != entry.len() {
+            return Err(E::CorruptedArchive {
+                position: entry.position(),
+                section: DecompressionError::Payload,
+            });
+        }
+        match checked {
+            Some(Ok(true)) | None => (),
+            Some(Ok(false)) => return Err(E::ChecksumMismatch(PathBuf::from(entry.name()))),
+            Some(Err(e)) => return Err(E::ArchiveIo(e)),
+        }
+        count += 1;
+    }
+    match result {
+        // Header and footers are the only fixed-size sections, so their truncation is reported as I/O error by the parser
+        Err(E::ArchiveIo(io_err)) if io_err.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(E::CorruptedArchive {
+                position: archive.position(),
+                section: if count == 0 {
+                    DecompressionError::Header
+                } else {
+                    DecompressionError::Footer
+                },
+            })
+        }
+        Err(err) => Err(err),
+        Ok(()) if last_is_index => Ok(count - 1),
+        Ok(()) => Ok(count),
+    }
+}
+
 /// Decompresses DCA archive `archive_name` into `work_directory`.
 ///
-/// Has simple high-level interface that skips and logs out files that fail to extract - (see [`decompress_from`]
+/// Has simple high-level interface that skips and logs out files that fail to extract - (see [`decompress_from_seekable`]
 /// if more control over process is desired.
 ///
 /// On failure during extraction, already extracted files are kept while files in the middle of extraction
@@ -353,10 +1201,17 @@ pub fn decompress_files(
     archive_name: impl AsRef<Path>,
     work_directory: impl AsRef<Path>,
 ) -> Result<()> {
-    let archive_name = archive_name.as_ref();
-    let work_directory = work_directory.as_ref();
+    let mut fhandler = DefaultFileHandler::new(work_directory.as_ref());
+    decompress_files_with(archive_name, &mut fhandler)
+}
 
-    let mut fhandler = DefaultFileHandler::new(work_directory);
+/// Variant of [`decompress_files`] with custom [`FileHandler`], such as configured [`DefaultFileHandler`]
+/// or its [`FilterFileHandler`] wrapper
+pub fn decompress_files_with(
+    archive_name: impl AsRef<Path>,
+    fhandler: &mut impl FileHandler,
+) -> Result<()> {
+    let archive_name = archive_name.as_ref();
     let ehandler = DefaultErrorHandler::new(archive_name);
 
     let arch = File::open(archive_name).map_err(|e| {
@@ -366,10 +1221,7 @@ pub fn decompress_files(
     })?;
     let mut reader = io::BufReader::new(arch);
 
-    decompress_from(&mut reader, &mut fhandler, &ehandler).map_err(|e| {
-        ehandler.on_fatal(&e);
-        e
-    })
+    decompress_from_seekable(&mut reader, fhandler, &ehandler).inspect_err(|e| ehandler.on_fatal(e))
 }
 
 #[cfg(test)]
@@ -542,4 +1394,450 @@ mod tests {
             results.into_iter(),
         );
     }
+
+    #[test]
+    fn test_streaming() {
+        // Plain slices are BufRead but not Seek
+        let contents: &[u8] = b"DCA\nfirst\n3\n123\nsecond\n5\nhello\nthird\n1\nx\n";
+        let mut names = Vec::new();
+        let mut fhandler = CallbackFileHandler(|name, _len, reader| {
+            // Read only part of the payload, rest shall be drained
+            let mut buf = [0u8; 2];
+            reader.read_exact(&mut buf[..1]).map_err(E::ArchiveIo)?;
+            names.push((name.to_owned(), buf[0]));
+            Ok(())
+        });
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                ("first".to_owned(), b'1'),
+                ("second".to_owned(), b'h'),
+                ("third".to_owned(), b'x')
+            ]
+        );
+
+        let contents: &[u8] = b"DCA\nfoo\n1000\nbar";
+        let err = decompress_from(
+            &mut { contents },
+            &mut CallbackFileHandler(|_, _, _| Ok(())),
+            &std_errors(),
+        )
+        .unwrap_err();
+        match err {
+            ArchiveError::CorruptedArchive {
+                position: 16,
+                section: DecompressionError::Payload,
+            } => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_index_name() {
+        let entries = |contents: &[u8]| {
+            let mut found = Vec::new();
+            let mut fhandler = CallbackFileHandler(|name, _len, reader| {
+                let mut payload = String::new();
+                reader.read_to_string(&mut payload).map_err(E::ArchiveIo)?;
+                found.push(format!("{}={}", name, payload));
+                Ok(())
+            });
+            decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+            assert_eq!(verify(&mut { contents }).unwrap(), found.len());
+            // Seekable archives find the index upfront, skipping the same entry
+            let mut seen = Vec::new();
+            let mut fhandler = CallbackFileHandler(|name, _len, _reader| {
+                seen.push(name.to_owned());
+                Ok(())
+            });
+            decompress_from_seekable(&mut Cursor::new(contents), &mut fhandler, &std_errors())
+                .unwrap();
+            assert_eq!(seen.len(), found.len());
+            found
+        };
+
+        // User files named like the index are extracted, wherever they are
+        assert_eq!(
+            entries(b"DCA\n.dca-index\n4\nuser\nfoo\n1\nx\n"),
+            [".dca-index=user", "foo=x"]
+        );
+        assert_eq!(
+            entries(b"DCA\nfoo\n1\nx\n.dca-index\n4\nuser\n"),
+            ["foo=x", ".dca-index=user"]
+        );
+
+        let mut writer = crate::index::CountingWriter::new(Vec::new());
+        writer.write_all(b"DCA\nfoo\n1\nx\n").unwrap();
+        let index = [crate::index::IndexEntry {
+            name: "foo".to_owned(),
+            offset: 4,
+            len: 1,
+        }];
+        crate::index::write_index(&mut writer, &index).unwrap();
+        let mut archive = writer.into_inner();
+        assert_eq!(entries(&archive), ["foo=x"]);
+
+        // Index followed by another entry is no longer the index
+        archive.extend_from_slice(b"bar\n0\n\n");
+        let found = entries(&archive);
+        assert_eq!(found.len(), 3);
+        assert!(found[1].starts_with(".dca-index=4 1 foo\n"));
+
+        // Large entries aren't held in memory
+        let len = MAX_HELD_INDEX as usize + 1;
+        let mut archive = format!("DCA\n.dca-index\n{}\n", len).into_bytes();
+        archive.resize(archive.len() + len, b'a');
+        archive.push(b'\n');
+        assert_eq!(entries(&archive).len(), 1);
+    }
+
+    #[test]
+    fn test_filter() {
+        let dir = make_dir();
+
+        let mut contents = Cursor::new(
+            b"DCA\nreport-1.csv\n1\n1\nnotes\n3\nabc\nreport-2.csv\n1\n2\nreport.txt\n0\n\n",
+        );
+        let selection = crate::select::Selection::new().glob("report-*.csv");
+        let mut fhandler = FilterFileHandler::new(files(&dir), |name| selection.matches(name));
+        decompress_from_seekable(&mut contents, &mut fhandler, &std_errors()).unwrap();
+
+        assert_eq!(dir_size(&dir), 2);
+        dir.child("report-1.csv").assert("1");
+        dir.child("report-2.csv").assert("2");
+    }
+
+    #[test]
+    fn test_extract_entry() {
+        let mut contents = Cursor::new(b"DCA\nfoo\n3\n123\nbar\n2\nab\nfoo\n1\nx\n");
+
+        let mut out = Vec::new();
+        extract_entry(&mut contents, "bar", None, &mut out).unwrap();
+        assert_eq!(out, b"ab");
+
+        let mut out = Vec::new();
+        contents.set_position(0);
+        extract_entry(&mut contents, "foo", Some(1), &mut out).unwrap();
+        assert_eq!(out, b"x");
+
+        contents.set_position(0);
+        match extract_entry(&mut contents, "foo", None, &mut Vec::new()).unwrap_err() {
+            ArchiveError::AmbiguousEntry(name, 2) if name == "foo" => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+        contents.set_position(0);
+        match extract_entry(&mut contents, "foo", Some(2), &mut Vec::new()).unwrap_err() {
+            ArchiveError::EntryNotFound(name) if name == "foo" => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+        contents.set_position(0);
+        match extract_entry(&mut contents, "baz", None, &mut Vec::new()).unwrap_err() {
+            ArchiveError::EntryNotFound(name) if name == "baz" => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let check = |contents: &[u8]| verify(&mut { contents });
+
+        assert_eq!(check(b"DCA\n").unwrap(), 0);
+        assert_eq!(check(b"DCA\nfoo\n3\n123\nbar\n0\n\n").unwrap(), 2);
+
+        use DecompressionError::*;
+        let cases: &[(&[u8], FilePosition, DecompressionError)] = &[
+            (b"", 0, Header),
+            (b"DCB\n", 0, Header),
+            (b"DCA\nfoo\nbar\n", 8, FileSize),
+            (b"DCA\nfoo\n5\n123", 13, Payload),
+            (b"DCA\nfoo\n3\n123", 13, Footer),
+            (b"DCA\nfoo\n3\n1234\n", 13, Footer),
+        ];
+        for (contents, expected_position, expected_section) in cases {
+            match check(contents).unwrap_err() {
+                ArchiveError::CorruptedArchive { position, section }
+                    if position == *expected_position && section == *expected_section => {}
+                e => panic!("Unexpected error {:?} for {:?}", e, contents),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsafe_names() {
+        for name in &["", ".", "..", "../x", "/abs", "a\0b", "a\nb"] {
+            assert!(!is_safe_name(name), "{:?}", name);
+            assert!(is_safe_name(&sanitize_name(name)), "{:?}", name);
+        }
+        assert!(is_safe_name("..."));
+        assert_eq!(sanitize_name("../x"), ".._x");
+        assert_eq!(sanitize_name(".."), "__");
+
+        let contents: &[u8] = b"DCA\nok\n1\n1\n..\n1\n2\n../../x\n1\n3\n";
+
+        let dir = make_dir();
+        let err = decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap_err();
+        match err {
+            ArchiveError::UnsafeEntryName(name) if name == ".." => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+
+        let dir = make_dir();
+        let mut fhandler = files(&dir).unsafe_names(UnsafeNamePolicy::Skip);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(dir_size(&dir), 1);
+
+        let dir = make_dir();
+        let mut fhandler = files(&dir).unsafe_names(UnsafeNamePolicy::Sanitize);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(dir_size(&dir), 3);
+        dir.child("__").assert("2");
+        dir.child(".._.._x").assert("3");
+    }
+
+    #[test]
+    fn test_overwrite() {
+        assert_eq!(numbered_name("notes.txt", 2), "notes (2).txt");
+        assert_eq!(numbered_name(".hidden", 3), ".hidden (3)");
+
+        let contents: &[u8] = b"DCA\na.txt\n3\nnew\nb\n1\nx\n";
+        let extract = |policy| {
+            let dir = make_dir();
+            dir.child("a.txt").write_str("ol").unwrap();
+            dir.child("b").write_str("old").unwrap();
+            let mut fhandler = files(&dir).overwrite(policy);
+            let res = decompress_from(&mut { contents }, &mut fhandler, &std_errors());
+            (dir, res)
+        };
+
+        let (dir, res) = extract(OverwritePolicy::Overwrite);
+        res.unwrap();
+        dir.child("a.txt").assert("new");
+        dir.child("b").assert("x");
+
+        let (dir, res) = extract(OverwritePolicy::Skip);
+        res.unwrap();
+        dir.child("a.txt").assert("ol");
+        dir.child("b").assert("old");
+
+        let (dir, res) = extract(OverwritePolicy::KeepLarger);
+        res.unwrap();
+        dir.child("a.txt").assert("new");
+        dir.child("b").assert("old");
+
+        let (dir, res) = extract(OverwritePolicy::Rename);
+        res.unwrap();
+        assert_eq!(dir_size(&dir), 4);
+        dir.child("a.txt").assert("ol");
+        dir.child("a (2).txt").assert("new");
+        dir.child("b (2)").assert("x");
+
+        let (dir, res) = extract(OverwritePolicy::Fail);
+        match res.unwrap_err() {
+            ArchiveError::FileExists(path) if path.ends_with("a.txt") => (),
+            e => panic!("Unexpected error type {:?}", e),
+        }
+        dir.child("a.txt").assert("ol");
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let contents: &[u8] = b"DCA\na.txt\n1\n1\nb\n0\n\na.txt\n1\n2\na.txt\n1\n3\n";
+        let extract = |policy| {
+            let dir = make_dir();
+            let mut fhandler = files(&dir).duplicates(policy);
+            decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+            dir
+        };
+
+        let dir = extract(DuplicatePolicy::KeepFirst);
+        assert_eq!(dir_size(&dir), 2);
+        dir.child("a.txt").assert("1");
+
+        // Entry that fails to extract doesn't count as the first one
+        let dir = make_dir();
+        let mut fhandler = files(&dir).duplicates(DuplicatePolicy::KeepFirst);
+        let contents: &[u8] = b"DCA2\na.txt\ncrc32=00000000\n1\n1\na.txt\n1\n2\na.txt\n1\n3\n";
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(dir_size(&dir), 1);
+        dir.child("a.txt").assert("2");
+
+        let dir = extract(DuplicatePolicy::KeepLast);
+        assert_eq!(dir_size(&dir), 2);
+        dir.child("a.txt").assert("3");
+
+        let dir = extract(DuplicatePolicy::Number);
+        assert_eq!(dir_size(&dir), 4);
+        dir.child("a.txt").assert("1");
+        dir.child("a (2).txt").assert("2");
+        dir.child("a (3).txt").assert("3");
+
+        // Own files are replaced regardless of overwrite policy
+        let dir = make_dir();
+        let mut fhandler = files(&dir).overwrite(OverwritePolicy::Fail);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        dir.child("a.txt").assert("3");
+    }
+
+    #[test]
+    fn test_atomic() {
+        let dir = make_dir();
+        dir.child("a").write_str("old").unwrap();
+
+        // Truncated payload neither replaces existing file nor leaves temporary one behind
+        let contents: &[u8] = b"DCA\na\n10\nnew";
+        decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap_err();
+        assert_eq!(dir_size(&dir), 1);
+        dir.child("a").assert("old");
+
+        let contents: &[u8] = b"DCA\na\n3\nnew\n";
+        let mut fhandler = files(&dir).fsync(true);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(dir_size(&dir), 1);
+        dir.child("a").assert("new");
+    }
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(numbered_name("dir.d/notes.txt", 2), "dir.d/notes (2).txt");
+        assert_eq!(numbered_name("dir.d/notes", 2), "dir.d/notes (2)");
+        assert!(is_safe_path("a/b/c"));
+        assert!(!is_safe_path("a/../c"));
+        assert!(!is_safe_path("/a"));
+        assert_eq!(sanitize_path("/a/../c"), "a/__/c");
+
+        let contents: &[u8] = b"DCA\na/b/c\n1\n1\na/d\n1\n2\n../x\n1\n3\n";
+
+        // Without paths extension, names with slashes are unsafe
+        let dir = make_dir();
+        let mut fhandler = files(&dir).unsafe_names(UnsafeNamePolicy::Skip);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        assert_eq!(dir_size(&dir), 0);
+
+        let dir = make_dir();
+        let mut fhandler = files(&dir).paths(true);
+        let err = decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap_err();
+        assert!(matches!(err, ArchiveError::UnsafeEntryName(name) if name == "../x"));
+        dir.child("a/b/c").assert("1");
+        dir.child("a/d").assert("2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_paths_symlink() {
+        let dir = make_dir();
+        let outside = make_dir();
+        std::os::unix::fs::symlink(outside.path(), dir.child("link")).unwrap();
+
+        let contents: &[u8] = b"DCA\nlink/x\n1\n1\n";
+        let mut fhandler = files(&dir).paths(true);
+        let err = decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap_err();
+        assert!(matches!(err, ArchiveError::UnsafeEntryName(..)));
+        assert_eq!(dir_size(&outside), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let contents: &[u8] = b"DCA2\na\nmtime=1700000000\nmode=4640\n1\n1\nb\n1\n2\n";
+        let dir = make_dir();
+        let mut fhandler = files(&dir).permissions(true);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        let meta = fs::metadata(dir.child("a")).unwrap();
+        assert_eq!(
+            meta.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o640);
+        dir.child("b").assert("2");
+
+        // Permissions are left to the umask by default
+        let dir = make_dir();
+        decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap();
+        let meta = fs::metadata(dir.child("a")).unwrap();
+        assert_eq!(
+            meta.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        let default_mode = fs::metadata(dir.child("b")).unwrap().permissions().mode();
+        assert_eq!(meta.permissions().mode(), default_mode);
+
+        let dir = make_dir();
+        let mut fhandler = files(&dir).metadata(false);
+        decompress_from(&mut { contents }, &mut fhandler, &std_errors()).unwrap();
+        let meta = fs::metadata(dir.child("a")).unwrap();
+        assert_ne!(
+            meta.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let contents: &[u8] =
+            b"DCA2\na\ncrc32=83dcefb7\n1\n1\nb\ncrc32=83DCEFB7\n1\n2\nc\nsha512=00\n1\n3\n";
+        let dir = make_dir();
+        decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap();
+        // Corrupted entry is skipped, unknown checksum isn't checked
+        dir.child("a").assert("1");
+        dir.child("b").assert(predicates::path::missing());
+        dir.child("c").assert("3");
+
+        let err = verify(&mut { contents }).unwrap_err();
+        assert!(matches!(err, ArchiveError::ChecksumMismatch(name) if name == Path::new("b")));
+        assert_eq!(verify(&mut &contents[..26]).unwrap(), 1);
+
+        let mut sink = Vec::new();
+        let err = extract_entry(&mut Cursor::new(contents), "b", None, &mut sink).unwrap_err();
+        assert!(matches!(err, ArchiveError::ChecksumMismatch(..)));
+
+        // Without support of SHA-256, its checksum is left unchecked with a warning
+        let contents: &[u8] = b"DCA2\nd\nsha256=00\n1\n4\n";
+        decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap();
+        if cfg!(feature = "sha256") {
+            dir.child("d").assert(predicates::path::missing());
+            assert!(verify(&mut { contents }).is_err());
+        } else {
+            dir.child("d").assert("4");
+            assert_eq!(verify(&mut { contents }).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_undecodable() {
+        let contents: &[u8] = b"DCA2\na\ncodec=foo\n3\nabc\nb\n1\nx\n";
+        let dir = make_dir();
+        decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap();
+        // Entry that can't be decoded is skipped, the rest of the archive is still usable
+        dir.child("a").assert(predicates::path::missing());
+        dir.child("b").assert("x");
+
+        let mut fhandler = CallbackFileHandler(|_, _, reader| {
+            io::copy(reader, &mut io::sink()).map_err(E::ArchiveIo)?;
+            Ok(())
+        });
+        match decompress_from(&mut { contents }, &mut fhandler, &StrictErrorHandler).unwrap_err() {
+            ArchiveError::BadFileIo(name, e) => {
+                assert_eq!(name, Path::new("a"));
+                assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+
+        #[cfg(feature = "deflate")]
+        {
+            let contents: &[u8] = b"DCA2\na\ncodec=deflate\nsize=3\n3\n\xff\xff\xff\nb\n1\nx\n";
+            let dir = make_dir();
+            decompress_from(&mut { contents }, &mut files(&dir), &std_errors()).unwrap();
+            dir.child("a").assert(predicates::path::missing());
+            dir.child("b").assert("x");
+
+            // Truncated payload is still a problem of the archive
+            let err = decompress_from(&mut &contents[..31], &mut files(&dir), &std_errors());
+            assert!(matches!(err, Err(ArchiveError::CorruptedArchive { .. })));
+        }
+    }
 }
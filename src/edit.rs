@@ -0,0 +1,292 @@
+//! Modifications of existing DCA archives - deleting and renaming entries
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, prelude::*, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::error::{
+    into_dca_filename, ArchiveError, DcaFilenameError, DecompressionError, FilePosition, Result,
+};
+use crate::index::{locate_index, write_index, CountingWriter, IndexEntry, INDEX_NAME};
+use crate::reader::ArchiveReader;
+use crate::tempfile::TempFile;
+use crate::writer::write_entry_header;
+
+use ArchiveError as E;
+
+/// Change of single archive entry, see [`edit_archive`]
+#[derive(Debug, PartialEq)]
+pub enum EntryEdit {
+    /// Entry is kept as is
+    Keep,
+    /// Entry is left out
+    Delete,
+    /// Entry is kept under a new name
+    Rename(String),
+}
+
+/// Rewrites the archive, letting `edit` decide what happens to each entry based on its name.
+///
+/// Payloads are copied straight from the original archive into a temporary file next to it, which then
+/// atomically replaces the original. On failure, the original archive stays untouched.
+/// Archives in extended format keep it, along with attributes of their entries.
+/// Index of the archive (see [`crate::index`]) is written anew, if the archive contains one.
+///
+/// New names are validated with [`into_dca_filename`]. If the archive has index, entries can't be renamed to
+/// [`INDEX_NAME`].
+pub fn edit_archive(
+    archive_name: impl AsRef<Path>,
+    edit: impl FnMut(&str) -> EntryEdit,
+) -> Result<()> {
+    rewrite(archive_name.as_ref(), &[], edit)
+}
+
+/// Implementation of [`edit_archive`], failing with [`ArchiveError::EntryNotFound`] before anything is written
+/// if any of `required` names isn't present in the archive
+fn rewrite(
+    archive_name: &Path,
+    required: &[&str],
+    mut edit: impl FnMut(&str) -> EntryEdit,
+) -> Result<()> {
+    let arch = File::open(archive_name).map_err(E::ArchiveIo)?;
+    let permissions = arch.metadata().map_err(E::ArchiveIo)?.permissions();
+
+    // Collect layout of the whole archive first, so that malformed archives are left untouched
+    let mut reader = io::BufReader::new(arch);
+    let index_offset = locate_index(&mut reader)?;
+    reader.rewind().map_err(E::ArchiveIo)?;
+    let mut archive = ArchiveReader::new_seekable(reader)?;
+    let mut layout = Vec::new();
+    for entry in archive.entries() {
+        let entry = entry?;
+        if Some(entry.start()) == index_offset {
+            continue;
+        }
+        layout.push((
+            entry.name().to_owned(),
+            entry.attributes().to_vec(),
+            entry.offset(),
+            entry.len(),
+        ));
+    }
+    if let Some(name) = required
+        .iter()
+        .find(|required| !layout.iter().any(|(name, ..)| name == *required))
+    {
+        return Err(E::EntryNotFound((*name).to_owned()));
+    }
+    let header: &[u8] = if archive.is_extended() {
+        b"DCA2\n"
+    } else {
+        b"DCA\n"
+    };
+    let mut arch = archive.into_inner().into_inner();
+
+    let mut temp = TempFile::new_sibling(archive_name).map_err(E::ArchiveIo)?;
+    let mut writer = CountingWriter::new(io::BufWriter::new(temp.file()));
+    writer.write_all(header).map_err(E::ArchiveIo)?;
+    let mut index = index_offset.map(|_| Vec::new());
+    for (name, attributes, offset, len) in layout {
+        let name = match edit(&name) {
+            EntryEdit::Keep => name,
+            EntryEdit::Delete => continue,
+            EntryEdit::Rename(new_name) => {
+                into_dca_filename(OsStr::new(&new_name))
+                    .map_err(|e| E::InvalidDcaFilename(PathBuf::from(&new_name), e))?;
+                if index.is_some() && new_name == INDEX_NAME {
+                    return Err(E::InvalidDcaFilename(
+                        PathBuf::from(new_name),
+                        DcaFilenameError::Reserved,
+                    ));
+                }
+                new_name
+            }
+        };
+        let new_offset = writer.count();
+        copy_entry(&mut arch, &mut writer, &name, &attributes, offset, len)?;
+        if let Some(index) = &mut index {
+            index.push(IndexEntry {
+                name,
+                offset: new_offset,
+                len,
+            });
+        }
+    }
+    if let Some(index) = index {
+        write_index(&mut writer, &index)?;
+    }
+    writer.flush().map_err(E::ArchiveIo)?;
+    drop(writer);
+
+    temp.file()
+        .set_permissions(permissions)
+        .map_err(E::ArchiveIo)?;
+    temp.persist(archive_name).map_err(E::ArchiveIo)
+}
+
+/// Writes entry whose payload is stored at `offset` in the `source` archive
+fn copy_entry(
+    source: &mut File,
+    writer: &mut impl Write,
+    name: &str,
+    attributes: &[(String, String)],
+    offset: FilePosition,
+    len: FilePosition,
+) -> Result<()> {
+    write_entry_header(writer, name, attributes, len).map_err(E::ArchiveIo)?;
+    source.seek(SeekFrom::Start(offset)).map_err(E::ArchiveIo)?;
+    let copied = io::copy(&mut source.take(len), writer).map_err(E::ArchiveIo)?;
+    if copied != len {
+        return Err(E::CorruptedArchive {
+            position: offset + copied,
+            section: DecompressionError::Payload,
+        });
+    }
+    writer.write_all(b"\n").map_err(E::ArchiveIo)
+}
+
+/// Removes all entries with given names from the archive
+///
+/// Fails with [`ArchiveError::EntryNotFound`] if any of the names isn't in the archive.
+/// See [`edit_archive`] for details.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::edit::delete_entries;
+///
+/// delete_entries("archive.dca", &["obsolete.txt"])
+///     .expect("failed to delete the entry");
+/// ```
+pub fn delete_entries(archive_name: impl AsRef<Path>, names: &[impl AsRef<str>]) -> Result<()> {
+    let required: Vec<&str> = names.iter().map(|name| name.as_ref()).collect();
+
+    rewrite(archive_name.as_ref(), &required, |entry| {
+        if names.iter().any(|name| name.as_ref() == entry) {
+            EntryEdit::Delete
+        } else {
+            EntryEdit::Keep
+        }
+    })
+}
+
+/// Renames all entries according to list of `(old, new)` name pairs
+///
+/// Fails with [`ArchiveError::EntryNotFound`] if any of the old names isn't in the archive.
+/// See [`edit_archive`] for details.
+pub fn rename_entries(
+    archive_name: impl AsRef<Path>,
+    renames: &[(impl AsRef<str>, impl AsRef<str>)],
+) -> Result<()> {
+    let required: Vec<&str> = renames.iter().map(|(old, _)| old.as_ref()).collect();
+
+    rewrite(archive_name.as_ref(), &required, |entry| {
+        match renames.iter().find(|(old, _)| old.as_ref() == entry) {
+            Some((_, new)) => EntryEdit::Rename(new.as_ref().to_owned()),
+            None => EntryEdit::Keep,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_fs::prelude::*;
+
+    use crate::error::DcaFilenameError;
+    use crate::testutils::*;
+
+    #[test]
+    fn test_delete() {
+        let dir = make_dir();
+        let arch = dir.child("archive.dca");
+        arch.write_binary(b"DCA\nfoo\n3\n123\nbar\n2\nab\nfoo\n1\nx\nbaz\n0\n\n")
+            .unwrap();
+
+        delete_entries(arch.path(), &["foo", "baz"]).unwrap();
+        arch.assert(b"DCA\nbar\n2\nab\n" as &[u8]);
+        assert_eq!(dir_size(&dir), 1);
+
+        match delete_entries(arch.path(), &["missing"]).unwrap_err() {
+            ArchiveError::EntryNotFound(name) if name == "missing" => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        arch.assert(b"DCA\nbar\n2\nab\n" as &[u8]);
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = make_dir();
+        let arch = dir.child("archive.dca");
+        arch.write_binary(b"DCA\nfoo\n3\n123\nbar\n2\nab\n")
+            .unwrap();
+
+        rename_entries(arch.path(), &[("foo", "renamed")]).unwrap();
+        arch.assert(b"DCA\nrenamed\n3\n123\nbar\n2\nab\n" as &[u8]);
+
+        match rename_entries(arch.path(), &[("bar", "a/b")]).unwrap_err() {
+            ArchiveError::InvalidDcaFilename(_, DcaFilenameError::InvalidChar('/', 1)) => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        arch.assert(b"DCA\nrenamed\n3\n123\nbar\n2\nab\n" as &[u8]);
+        assert_eq!(dir_size(&dir), 1);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let dir = make_dir();
+        let arch = dir.child("archive.dca");
+        arch.write_binary(b"DCA\nfoo\n30\n123\n").unwrap();
+
+        assert!(edit_archive(arch.path(), |_| EntryEdit::Delete).is_err());
+        arch.assert(b"DCA\nfoo\n30\n123\n" as &[u8]);
+        assert_eq!(dir_size(&dir), 1);
+    }
+
+    #[test]
+    fn test_extended() {
+        let dir = make_dir();
+        let arch = dir.child("archive.dca");
+        arch.write_binary(b"DCA2\nfoo\ncodec=zstd\nsize=10\n3\n123\nbar\n2\nab\n")
+            .unwrap();
+
+        rename_entries(arch.path(), &[("foo", "renamed")]).unwrap();
+        arch.assert(b"DCA2\nrenamed\ncodec=zstd\nsize=10\n3\n123\nbar\n2\nab\n" as &[u8]);
+        delete_entries(arch.path(), &["renamed"]).unwrap();
+        arch.assert(b"DCA2\nbar\n2\nab\n" as &[u8]);
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = make_dir();
+        let arch = dir.child("archive.dca");
+        let mut writer = CountingWriter::new(Vec::new());
+        writer.write_all(b"DCA\nfoo\n3\n123\nbar\n2\nab\n").unwrap();
+        let entries = [("foo", 4, 3), ("bar", 14, 2)].map(|(name, offset, len)| IndexEntry {
+            name: name.to_owned(),
+            offset,
+            len,
+        });
+        write_index(&mut writer, &entries).unwrap();
+        arch.write_binary(&writer.into_inner()).unwrap();
+
+        delete_entries(arch.path(), &["foo"]).unwrap();
+        let mut reader = io::BufReader::new(File::open(arch.path()).unwrap());
+        let index = crate::index::read_index(&mut reader).unwrap().unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!((index[0].name.as_str(), index[0].offset), ("bar", 4));
+
+        match rename_entries(arch.path(), &[("bar", INDEX_NAME)]).unwrap_err() {
+            ArchiveError::InvalidDcaFilename(_, DcaFilenameError::Reserved) => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+
+        // Without index, entry of that name is a regular file
+        arch.write_binary(b"DCA\n.dca-index\n3\n123\nbar\n2\nab\n")
+            .unwrap();
+        delete_entries(arch.path(), &["bar"]).unwrap();
+        arch.assert(b"DCA\n.dca-index\n3\n123\n" as &[u8]);
+    }
+}
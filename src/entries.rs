@@ -2,25 +2,59 @@
 
 use std::io::{BufRead, Seek};
 
-use crate::decompress::{decompress_from, CallbackFileHandler};
+use crate::decompress::{decompress_from, decompress_from_seekable, FileDescriptor, FileHandler};
 use crate::error::{FilePosition, Handler as ErrorHandler, Result};
 
-/// Extracts list of archive's entries as name-size pairs
+/// Information about single entry of the archive, see [`archive_entries`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct EntryInfo {
+    /// Entry filename
+    pub name: String,
+    /// Size of the original content in bytes
+    pub len: FilePosition,
+    /// Size of the payload as stored in the archive, differs from `len` for compressed entries
+    pub stored_len: FilePosition,
+}
+
+/// [`FileHandler`] collecting information about entries, without reading their payloads
+struct InfoFileHandler(Vec<EntryInfo>);
+
+impl FileHandler for InfoFileHandler {
+    fn on_file<R: BufRead>(&mut self, file: FileDescriptor<'_, R>) -> Result<()> {
+        self.0.push(EntryInfo {
+            name: file.name.to_owned(),
+            len: file.len,
+            stored_len: file.stored_len,
+        });
+        Ok(())
+    }
+}
+
+/// Extracts list of archive's entries along with their sizes
 ///
+/// Works on any forward-only reader, payloads are read and discarded.
+/// Archives wrapped in compressed stream are recognized, see [`crate::codec`].
 /// See also CLI's method `list_files` for high-level usage.
 pub fn archive_entries(
-    reader: &mut (impl BufRead + Seek),
+    reader: &mut impl BufRead,
     error_handler: &impl ErrorHandler,
-) -> Result<Vec<(String, FilePosition)>> {
-    let mut names = Vec::new();
-
-    let mut fhandler = CallbackFileHandler(|name, len, _reader| {
-        names.push((name.to_owned(), len));
-        Ok(())
-    });
+) -> Result<Vec<EntryInfo>> {
+    let mut fhandler = InfoFileHandler(Vec::new());
     decompress_from(reader, &mut fhandler, error_handler)?;
 
-    Ok(names)
+    Ok(fhandler.0)
+}
+
+/// Variant of [`archive_entries`] that seeks over payloads instead of reading them
+pub fn archive_entries_seekable(
+    reader: &mut (impl BufRead + Seek),
+    error_handler: &impl ErrorHandler,
+) -> Result<Vec<EntryInfo>> {
+    let mut fhandler = InfoFileHandler(Vec::new());
+    decompress_from_seekable(reader, &mut fhandler, error_handler)?;
+
+    Ok(fhandler.0)
 }
 
 #[cfg(test)]
@@ -43,8 +77,30 @@ mod tests {
         let mut arch = Cursor::new("DCA\nhello\n3\n123\nworld\n5\n12345\nempty\n0\n\n");
         let names = archive_entries(&mut arch, &std_errors()).unwrap();
         assert_eq_iters(
-            names.iter().map(|(a, b)| (a.borrow(), *b)),
+            names.iter().map(|e| (e.name.borrow(), e.len)),
             vec![("hello", 3), ("world", 5), ("empty", 0)].into_iter(),
         );
     }
+
+    #[test]
+    fn test_streaming() {
+        let contents: &[u8] = b"DCA\nhello\n3\n123\nworld\n5\n12345\nempty\n0\n\n";
+        let names = archive_entries(&mut { contents }, &std_errors()).unwrap();
+        let seeked = archive_entries_seekable(&mut Cursor::new(contents), &std_errors()).unwrap();
+        assert_eq!(names, seeked);
+        assert_eq_iters(
+            names.iter().map(|e| (e.name.borrow(), e.len)),
+            vec![("hello", 3), ("world", 5), ("empty", 0)].into_iter(),
+        );
+    }
+
+    #[test]
+    fn test_extended() {
+        let contents: &[u8] = b"DCA2\nhello\ncodec=zstd\nsize=10\n3\nabc\nplain\nkey=\n2\nhi\n";
+        let names = archive_entries(&mut { contents }, &std_errors()).unwrap();
+        assert_eq_iters(
+            names.iter().map(|e| (e.name.borrow(), e.len, e.stored_len)),
+            vec![("hello", 10, 3), ("plain", 2, 2)].into_iter(),
+        );
+    }
 }
@@ -15,12 +15,12 @@ pub(crate) use log::{error, warn};
 mod log {
     macro_rules! error {
         ($($any:tt)*) => {
-            eprintln!($($any)*);
+            eprintln!($($any)*)
         }
     }
     macro_rules! warning {
         ($($any:tt)*) => {
-            eprintln!($($any)*);
+            eprintln!($($any)*)
         }
     }
     pub(crate) use {error, warning};
@@ -49,6 +49,23 @@ pub enum ArchiveError {
     BadFileIo(PathBuf, io::Error),
     /// Filename of archive contents doesn't conform to expected requirements
     InvalidDcaFilename(PathBuf, DcaFilenameError),
+    /// Requested entry is not present in the archive
+    EntryNotFound(String),
+    /// Requested entry name is present in the archive more than once, along with number of occurrences
+    AmbiguousEntry(String, usize),
+    /// Entry name found in the archive can't be safely used as a filename, as it could refer to a file outside of target directory
+    UnsafeEntryName(String),
+    /// Extracted file would replace already existing file
+    FileExists(PathBuf),
+    /// Extraction of file was skipped as the file already exists
+    FileSkipped(PathBuf),
+    /// Entry of the same name was already added into the archive
+    DuplicateEntry(String),
+    /// Two different files would be stored under the same entry name
+    NameCollision(String, PathBuf, PathBuf),
+    /// Content of archive entry doesn't match its checksum, see [`crate::checksum`].
+    /// Path is the one entry was extracted into
+    ChecksumMismatch(PathBuf),
 }
 
 /// Standard conveniency alias
@@ -71,6 +88,24 @@ impl Display for ArchiveError {
                 path.file_name().unwrap_or_else(|| OsStr::new("\"\"")),
                 problem
             ),
+            EntryNotFound(name) => write!(f, "archive doesn't contain entry {:?}", name),
+            UnsafeEntryName(name) => write!(f, "entry name {:?} is unsafe to extract", name),
+            FileExists(path) => write!(f, "file {:?} already exists", path),
+            FileSkipped(path) => write!(f, "file {:?} already exists, skipped", path),
+            DuplicateEntry(name) => write!(f, "archive already contains entry {:?}", name),
+            NameCollision(name, first, second) => write!(
+                f,
+                "files {:?} and {:?} would be both stored as {:?}",
+                first, second, name
+            ),
+            AmbiguousEntry(name, count) => write!(
+                f,
+                "archive contains {} entries named {:?}, choose one of them",
+                count, name
+            ),
+            ChecksumMismatch(path) => {
+                write!(f, "content of entry {:?} doesn't match its checksum", path)
+            }
         }
     }
 }
@@ -133,6 +168,10 @@ pub enum DcaFilenameError {
     NotUnicode,
     /// Unsupported character detected at certain position
     InvalidChar(char, usize),
+    /// Path contains empty, `.` or `..` component
+    InvalidPathComponent(String),
+    /// Name is reserved for the index of the archive, see [`crate::index::INDEX_NAME`]
+    Reserved,
 }
 
 impl Display for DcaFilenameError {
@@ -141,6 +180,10 @@ impl Display for DcaFilenameError {
         match self {
             NotUnicode => write!(f, "name is not valid UTF-8"),
             InvalidChar(ch, pos) => write!(f, "unsupported character '{}' at position {}", ch, pos),
+            InvalidPathComponent(component) => {
+                write!(f, "unsupported path component {:?}", component)
+            }
+            Reserved => write!(f, "name is reserved for the archive index"),
         }
     }
 }
@@ -162,15 +205,39 @@ pub fn into_dca_filename(name: &OsStr) -> Result<&str, DcaFilenameError> {
     }
 }
 
+/// Convert OS-specific relative path into DCA-compatible name for archives using paths extension
+///
+/// Unlike [`into_dca_filename`], `/` is accepted as a separator of path components. Path must not be absolute
+/// and its components must not be empty, `.` or `..`.
+pub fn into_dca_path(name: &OsStr) -> Result<&str, DcaFilenameError> {
+    use DcaFilenameError as O;
+    let name = name.to_str().ok_or(O::NotUnicode)?;
+    if let Some((pos, ch)) = name.chars().enumerate().find(|(_, ch)| *ch == '\n') {
+        return Err(O::InvalidChar(ch, pos));
+    }
+    if name.starts_with('/') {
+        return Err(O::InvalidChar('/', 0));
+    }
+    match name
+        .split('/')
+        .find(|component| ["", ".", ".."].contains(component))
+    {
+        Some(component) => Err(O::InvalidPathComponent(component.to_owned())),
+        None => Ok(name),
+    }
+}
+
 /// Lists various sections of DCA file format where problems during extraction could occur
 ///
 /// See DCA grammar (in project's README) for details
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(missing_docs)]
+#[non_exhaustive]
 pub enum DecompressionError {
     Header,
     FileName,
     FileSize,
+    Attribute,
     Payload,
     Footer,
 }
@@ -192,6 +259,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dca_path() {
+        assert_eq!(into_dca_path(OsStr::new("foo/bar")).unwrap(), "foo/bar");
+        assert_eq!(
+            into_dca_path(OsStr::new("/etc/passwd")).unwrap_err(),
+            DcaFilenameError::InvalidChar('/', 0)
+        );
+        for (path, component) in &[("a/../b", ".."), ("a//b", ""), ("a/", ""), ("./a", ".")] {
+            assert_eq!(
+                into_dca_path(OsStr::new(path)).unwrap_err(),
+                DcaFilenameError::InvalidPathComponent(component.to_string())
+            );
+        }
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_dca_filename_nonunicode() {
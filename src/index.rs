@@ -0,0 +1,260 @@
+//! Optional index of archive entries, allowing to seek straight to any of them
+//!
+//! Index is stored as the last entry of the archive, named [`INDEX_NAME`], so readers unaware of it just see
+//! one more file. Its payload contains one line per entry, `<offset> <size> <name>`, where offset is position
+//! of the entry's filename relative to the start of the archive and size is size of its payload as stored.
+//! Payload ends with fixed-size locator `DCA-INDEX <offset of the index entry, 20 digits>\n`,
+//! so the index is found by reading the last [`LOCATOR_LEN`] bytes of the archive (including its footer).
+//!
+//! Index is written by [`crate::compress::compress_into`] if enabled by [`crate::compress::EntryOptions::index`],
+//! kept up to date by [`crate::edit`] and [`crate::compress::append_files`] and used by
+//! [`crate::decompress::extract_entry`].
+//! Other readers of this crate skip the index entry, but only if it's the last entry and its locator points to it,
+//! other entries named [`INDEX_NAME`] are treated as any other file.
+
+use std::io::{self, prelude::*, SeekFrom};
+
+use crate::error::{warn, ArchiveError, FilePosition, Result};
+use crate::reader::ArchiveReader;
+use crate::writer::write_entry_header;
+
+use ArchiveError as E;
+
+/// Name of the entry containing the index
+pub const INDEX_NAME: &str = ".dca-index";
+
+/// Length of the locator at the end of indexed archive, including footer of the index entry
+pub const LOCATOR_LEN: usize = LOCATOR_PREFIX.len() + 20 + 2;
+
+const LOCATOR_PREFIX: &str = "DCA-INDEX ";
+
+/// Position of single entry in the archive
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct IndexEntry {
+    /// Entry name
+    pub name: String,
+    /// Position of the entry (its filename) relative to the start of the archive
+    pub offset: FilePosition,
+    /// Size of the payload as stored
+    pub len: FilePosition,
+}
+
+/// Writer keeping track of number of bytes written, so that offsets of entries are known
+pub(crate) struct CountingWriter<W> {
+    inner: W,
+    count: FilePosition,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self::with_count(inner, 0)
+    }
+    /// Creates writer continuing at position `count`
+    pub(crate) fn with_count(inner: W, count: FilePosition) -> Self {
+        Self { inner, count }
+    }
+    /// Number of bytes written so far
+    pub(crate) fn count(&self) -> FilePosition {
+        self.count
+    }
+    #[cfg(test)]
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as FilePosition;
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes index entry of `entries`, which should be the last entry of the archive
+pub(crate) fn write_index<W: Write>(
+    writer: &mut CountingWriter<W>,
+    entries: &[IndexEntry],
+) -> Result<()> {
+    let mut payload = Vec::new();
+    for entry in entries {
+        writeln!(payload, "{} {} {}", entry.offset, entry.len, entry.name).map_err(E::ArchiveIo)?;
+    }
+    writeln!(payload, "{}{:020}", LOCATOR_PREFIX, writer.count()).map_err(E::ArchiveIo)?;
+
+    write_entry_header(writer, INDEX_NAME, &[], payload.len() as FilePosition)
+        .and_then(|()| writer.write_all(&payload))
+        .and_then(|()| writer.write_all(b"\n"))
+        .map_err(E::ArchiveIo)
+}
+
+/// Parses offset of the index entry from the locator, without the footer
+fn parse_locator(locator: &[u8]) -> Option<FilePosition> {
+    let locator = std::str::from_utf8(locator).ok()?;
+    locator
+        .strip_prefix(LOCATOR_PREFIX)?
+        .strip_suffix('\n')?
+        .parse()
+        .ok()
+}
+
+/// Returns true if `payload` ends with locator pointing to `offset`, relative to the start of the archive.
+///
+/// Entry called [`INDEX_NAME`] starting at `offset` is the index if this holds and it's the last entry of the archive.
+pub(crate) fn is_locator_of(payload: &[u8], offset: FilePosition) -> bool {
+    payload.len() >= LOCATOR_LEN - 1
+        && parse_locator(&payload[payload.len() - (LOCATOR_LEN - 1)..]) == Some(offset)
+}
+
+/// Parses single line of the index
+fn parse_line(line: &str) -> Option<IndexEntry> {
+    let (offset, rest) = line.split_once(' ')?;
+    let (len, name) = rest.split_once(' ')?;
+    Some(IndexEntry {
+        name: name.to_owned(),
+        offset: offset.parse().ok()?,
+        len: len.parse().ok()?,
+    })
+}
+
+/// Finds the index entry of the archive starting at the current position of `reader`, without scanning the archive
+///
+/// Returns offset of the entry relative to the start of the archive, or `Ok(None)` if the archive has no valid index.
+/// Position of the reader is left unspecified.
+///
+/// Entries appended to the archive should be written at this offset instead of the end of the archive,
+/// followed by a new index, as the index has to stay the last entry.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::index::locate_index;
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// let mut archive = BufReader::new(File::open("archive.dca").unwrap());
+/// match locate_index(&mut archive).expect("reading failed") {
+///     Some(offset) => println!("Index starts at {}", offset),
+///     None => println!("Archive has no index"),
+/// }
+/// ```
+pub fn locate_index(reader: &mut (impl BufRead + Seek)) -> Result<Option<FilePosition>> {
+    let start = reader.stream_position().map_err(E::ArchiveIo)?;
+    let end = reader.seek(SeekFrom::End(0)).map_err(E::ArchiveIo)?;
+    if end < start + LOCATOR_LEN as FilePosition {
+        return Ok(None);
+    }
+    reader
+        .seek(SeekFrom::End(-(LOCATOR_LEN as i64)))
+        .map_err(E::ArchiveIo)?;
+    let mut locator = [0; LOCATOR_LEN];
+    reader.read_exact(&mut locator).map_err(E::ArchiveIo)?;
+    let offset = match parse_locator(&locator[..LOCATOR_LEN - 1]) {
+        Some(offset) if locator[LOCATOR_LEN - 1] == b'\n' && start + offset < end => offset,
+        _ => return Ok(None),
+    };
+
+    reader.seek(SeekFrom::Start(start)).map_err(E::ArchiveIo)?;
+    let mut archive = ArchiveReader::new_seekable(&mut *reader)?;
+    archive.seek_entry(start + offset)?;
+    let entry = match archive.entries().next() {
+        Some(entry) => entry?,
+        None => return Ok(None),
+    };
+    // Index has to span up to the end of the archive, otherwise it's left from before appending
+    if entry.name() != INDEX_NAME || entry.offset() + entry.len() + 1 != end {
+        return Ok(None);
+    }
+    Ok(Some(offset))
+}
+
+/// Reads index of the archive starting at the current position of `reader`, without scanning the archive
+///
+/// Returns `Ok(None)` if the archive has no index, or if it isn't valid, such as after appending to the archive.
+/// Position of the reader is left unspecified.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::index::read_index;
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// let mut archive = BufReader::new(File::open("archive.dca").unwrap());
+/// if let Some(index) = read_index(&mut archive).expect("reading failed") {
+///     for entry in index {
+///         println!("{} at {}", entry.name, entry.offset);
+///     }
+/// }
+/// ```
+pub fn read_index(reader: &mut (impl BufRead + Seek)) -> Result<Option<Vec<IndexEntry>>> {
+    let start = reader.stream_position().map_err(E::ArchiveIo)?;
+    let offset = match locate_index(reader)? {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    reader.seek(SeekFrom::Start(start)).map_err(E::ArchiveIo)?;
+    let mut archive = ArchiveReader::new_seekable(&mut *reader)?;
+    archive.seek_entry(start + offset)?;
+    let mut entry = archive.entries().next().expect("index entry was found")?;
+    let mut payload = String::new();
+    if entry.read_to_string(&mut payload).is_err() {
+        warn!("Index of the archive is not valid UTF-8, ignoring it");
+        return Ok(None);
+    }
+
+    let mut lines: Vec<&str> = payload.split_terminator('\n').collect();
+    lines.pop();
+    match lines.into_iter().map(parse_line).collect() {
+        Some(entries) => Ok(Some(entries)),
+        None => {
+            warn!("Index of the archive is malformed, ignoring it");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = CountingWriter::new(Vec::new());
+        writer
+            .write_all(b"DCA\nfoo\n3\n123\nbar baz\n0\n\n")
+            .unwrap();
+        let entries = [
+            IndexEntry {
+                name: "foo".to_owned(),
+                offset: 4,
+                len: 3,
+            },
+            IndexEntry {
+                name: "bar baz".to_owned(),
+                offset: 14,
+                len: 0,
+            },
+        ];
+        write_index(&mut writer, &entries).unwrap();
+        let archive = writer.into_inner();
+        assert!(archive.ends_with(b"DCA-INDEX 00000000000000000025\n\n"));
+
+        let index = read_index(&mut Cursor::new(&archive)).unwrap().unwrap();
+        assert_eq!(index, entries);
+        assert_eq!(crate::decompress::verify(&mut &archive[..]).unwrap(), 2);
+
+        // Appended entry hides the index
+        let mut appended = archive.clone();
+        appended.extend_from_slice(b"new\n0\n\n");
+        assert_eq!(read_index(&mut Cursor::new(&appended)).unwrap(), None);
+
+        assert_eq!(read_index(&mut Cursor::new(b"DCA\n")).unwrap(), None);
+    }
+}
@@ -15,14 +15,27 @@
 
 #![warn(missing_docs)]
 
+pub mod checksum;
+pub mod codec;
 pub mod compress;
 pub mod decompress;
+pub mod edit;
 pub mod entries;
 pub mod error;
+pub mod index;
+pub mod metadata;
+pub mod reader;
+pub mod salvage;
+pub mod select;
+pub mod walk;
+pub mod writer;
 
+mod tempfile;
 #[cfg(test)]
 mod testutils;
 
 pub use compress::compress_files;
 pub use decompress::decompress_files;
 pub use entries::archive_entries;
+pub use reader::ArchiveReader;
+pub use writer::ArchiveWriter;
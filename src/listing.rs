@@ -1,49 +1,84 @@
 //! Implements archive listing CLI feature
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader};
 use std::path::Path;
 
+use dca::compress::STDIN_PATH;
 use dca::decompress::DefaultErrorHandler;
-use dca::entries::archive_entries;
+use dca::entries::{archive_entries, archive_entries_seekable, EntryInfo};
 use dca::error::{ArchiveError, FilePosition, Result};
 
 use humansize::{file_size_opts::CONVENTIONAL as FSIZE_STYLE, FileSize};
 
 /// Defines ordering of archive's entries
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum ListingSort {
+    #[default]
     Unsorted,
     Name,
     Size,
 }
-impl Default for ListingSort {
-    fn default() -> Self {
-        ListingSort::Unsorted
-    }
-}
 
 fn fmt_file_size(pos: FilePosition) -> impl std::fmt::Display {
     // Should never be able to fail (library fails for negative numbers)
     pos.file_size(FSIZE_STYLE).unwrap()
 }
 
+/// Entry as seen by the listing, either from the archive or as a name-size pair
+trait Listed {
+    fn name(&self) -> &str;
+    fn size(&self) -> FilePosition;
+}
+
+impl<Stringlike: AsRef<str>> Listed for (Stringlike, FilePosition) {
+    fn name(&self) -> &str {
+        self.0.as_ref()
+    }
+    fn size(&self) -> FilePosition {
+        self.1
+    }
+}
+
+impl Listed for EntryInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn size(&self) -> FilePosition {
+        self.len
+    }
+}
+
 /// Applies the ListingSort sorting
 ///
 /// Note that names of entries can be nonunique - as this was deemed a pathological case, sorting order of these
 /// entries was left undefined for efficiency
-fn sort<Stringlike: Eq + Ord>(names: &mut Vec<(Stringlike, FilePosition)>, sorting: ListingSort) {
+fn sort(names: &mut [impl Listed], sorting: ListingSort) {
     match sorting {
-        ListingSort::Name => names.sort_unstable_by(|a, b| a.0.cmp(&b.0)),
-        ListingSort::Size => names.sort_unstable_by(|a, b| a.1.cmp(&b.1).reverse()),
+        ListingSort::Name => names.sort_unstable_by(|a, b| a.name().cmp(b.name())),
+        ListingSort::Size => names.sort_unstable_by(|a, b| a.size().cmp(&b.size()).reverse()),
         ListingSort::Unsorted => (),
     }
 }
 
-/// Extracts, sorts and prints archive's contents to standard output
+/// Collects names occurring more than once
+fn duplicate_names(names: &[impl Listed]) -> HashSet<&str> {
+    let mut counts = HashMap::new();
+    for entry in names {
+        *counts.entry(entry.name()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Extracts, sorts and prints archive's contents to standard output. Archive named `-` is read from standard input.
 ///
 /// Note that names of entries can be nonunique - as this was deemed a pathological case, sorting order of these
-/// entries was left undefined for efficiency
+/// entries was left undefined for efficiency. Such entries are flagged in the listing.
 ///
 /// # Example
 /// ```no_run
@@ -51,22 +86,42 @@ fn sort<Stringlike: Eq + Ord>(names: &mut Vec<(Stringlike, FilePosition)>, sorti
 /// ```
 /// Outputs the following (format may change)
 /// ```text
+/// file4 (2.10 KB, stored 310 B)
 /// file3 (130 B)
-/// file1 (50 B)
+/// file1 (50 B) [duplicate]
+/// file1 (8 B) [duplicate]
 /// file2 (5 B)
 /// ```
 pub fn list_files(archive_name: impl AsRef<Path>, sorting: ListingSort) -> Result<()> {
     let archive_name = archive_name.as_ref();
 
-    let arch = File::open(archive_name).map_err(ArchiveError::ArchiveIo)?;
-    let mut reader = BufReader::new(arch);
     let ehandler = DefaultErrorHandler::new(archive_name);
 
-    let mut names = archive_entries(&mut reader, &ehandler)?;
+    // Standard input can only be read forward
+    let mut names = if archive_name == Path::new(STDIN_PATH) {
+        archive_entries(&mut io::stdin().lock(), &ehandler)?
+    } else {
+        let arch = File::open(archive_name).map_err(ArchiveError::ArchiveIo)?;
+        archive_entries_seekable(&mut BufReader::new(arch), &ehandler)?
+    };
     sort(&mut names, sorting);
+    let duplicates = duplicate_names(&names);
 
-    for (name, size) in names {
-        println!("{} ({})", name, fmt_file_size(size));
+    for entry in &names {
+        let size = if entry.stored_len != entry.len {
+            format!(
+                "{}, stored {}",
+                fmt_file_size(entry.len),
+                fmt_file_size(entry.stored_len)
+            )
+        } else {
+            fmt_file_size(entry.len).to_string()
+        };
+        if duplicates.contains(entry.name.as_str()) {
+            println!("{} ({}) [duplicate]", entry.name, size);
+        } else {
+            println!("{} ({})", entry.name, size);
+        }
     }
     Ok(())
 }
@@ -92,4 +147,13 @@ mod tests {
         sort(&mut a, ListingSort::Size);
         assert_eq!(a, vec![("world", 5), ("hello", 3), ("empty", 0)]);
     }
+
+    #[test]
+    fn test_duplicates() {
+        let data: Vec<(&'static str, FilePosition)> =
+            vec![("a", 5), ("b", 3), ("a", 0), ("c", 1), ("b", 2)];
+        let mut duplicates: Vec<_> = duplicate_names(&data).into_iter().collect();
+        duplicates.sort_unstable();
+        assert_eq!(duplicates, vec!["a", "b"]);
+    }
 }
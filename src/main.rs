@@ -2,15 +2,18 @@
 
 use log::debug;
 use std::error::Error;
-use std::ffi::{OsStr, OsString};
+use std::ffi::OsString;
 use std::fmt::{self, Display};
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use dca::*;
 
 // CLI specific logic
 mod listing;
+mod manifest;
 use listing::{list_files, ListingSort};
 
 /// Helper for printing error chains
@@ -42,6 +45,31 @@ fn parse_args() -> clap::ArgMatches<'static> {
         .arg(
             Arg::from_usage("-d --decompress")
         )
+        .arg(
+            Arg::from_usage("-a --append")
+                .help("Appends files to the end of existing archive, given as the first of files. Takes the same options as compression, except for --files-from and --indexed, as index of the archive is kept up to date.")
+        )
+        .arg(
+            Arg::from_usage("--delete")
+                .help("Deletes entries given after the archive name from the archive.")
+        )
+        .arg(
+            Arg::from_usage("--rename")
+                .help("Renames entries of the archive. Each name change after the archive name is given as OLD=NEW, separated by the last '=' as in SRC=NAME.")
+        )
+        .arg(
+            Arg::from_usage("--cat")
+                .help("Writes payload of single entry to standard output. Takes archive name followed by entry name.")
+        )
+        .arg(
+            Arg::from_usage("--index [index]")
+                .requires("cat")
+                .help("Selects which of the entries with the same name is written, counting from 0.")
+        )
+        .arg(
+            Arg::from_usage("-t --test")
+                .help("Checks integrity of the archive without extracting anything.")
+        )
         .arg(
             Arg::from_usage("-l --list")
                 .help("Lists archive's contents.")
@@ -58,18 +86,148 @@ fn parse_args() -> clap::ArgMatches<'static> {
                 .requires("list")
         )
         .arg(
-            Arg::from_usage("<files>...")
-                .help("If decompressing or listing, should be ONLY name of the archive. If compressing, should be list of files.")
+            Arg::from_usage("--only [pattern]")
+                .multiple(true)
+                .number_of_values(1)
+                .help("When decompressing, extract only entries matching wildcard pattern. Can be repeated.")
+        )
+        .arg(
+            Arg::from_usage("--only-name [name]")
+                .multiple(true)
+                .number_of_values(1)
+                .help("When decompressing, extract only entries with exactly this name, even if it contains wildcard characters. Can be repeated.")
+        )
+        .arg(
+            Arg::from_usage("--salvage")
+                .help("When decompressing, recovers as much as possible from damaged archive. Exits with status 2 if some data were lost.")
+        )
+        .arg(
+            Arg::from_usage("--unsafe-names [policy]")
+                .possible_values(&["reject", "sanitize", "skip"])
+                .help("When decompressing, treatment of entries whose names could escape the output directory. Defaults to reject.")
+        )
+        .arg(
+            Arg::from_usage("--overwrite")
+                .help("When decompressing, replace existing files. This is the default.")
+        )
+        .arg(
+            Arg::from_usage("--no-clobber")
+                .help("When decompressing, keep existing files and skip the entries instead. When compressing, fail if the archive already exists.")
+        )
+        .arg(
+            Arg::from_usage("--force")
+                .help("When compressing, replace existing archive even if its name was chosen automatically")
+        )
+        .arg(
+            Arg::from_usage("--rename-existing")
+                .help("When decompressing, extract entries colliding with existing files under numbered name, such as 'notes (2).txt'")
+        )
+        .arg(
+            Arg::from_usage("--duplicates [policy]")
+                .possible_values(&["reject", "warn", "rename", "first", "last", "number"])
+                .help("When compressing, whether files with name already in the archive are rejected, added with a warning (default) or renamed. When decompressing, which of the entries sharing the same name to keep (first or last, the default), or number them all.")
+        )
+        .arg(
+            Arg::from_usage("--fsync")
+                .help("When decompressing, sync each extracted file to disk before moving it into place")
+        )
+        .arg(
+            Arg::from_usage("--manifest [file]")
+                .help("When compressing, adds files listed in the file, one SRC=NAME or SRC per line. Relative paths are resolved against the directory of the file")
+        )
+        .arg(
+            Arg::from_usage("-T --files-from [file]")
+                .conflicts_with_all(&["files", "manifest", "recursive"])
+                .help("When compressing, reads paths of files to add from the file, one per line, or from standard input if '-'")
+        )
+        .arg(
+            Arg::from_usage("--null")
+                .requires("files-from")
+                .help("Paths read by --files-from are separated by NUL characters instead of newlines")
+        )
+        .arg(
+            Arg::from_usage("--compression [codec]")
+                .possible_values(&["deflate", "zstd"])
+                .help("When compressing, compresses each entry individually, creating archive in extended format")
+        )
+        .arg(
+            Arg::from_usage("--metadata")
+                .help("When compressing, stores modification times and permissions of files, creating archive in extended format")
+        )
+        .arg(
+            Arg::from_usage("--checksum [algorithm]")
+                .possible_values(&["crc32", "sha256"])
+                .help("When compressing, stores checksum of each entry that's checked on extraction, creating archive in extended format")
+        )
+        .arg(
+            Arg::from_usage("--indexed")
+                .conflicts_with("append")
+                .help("When compressing, ends the archive with index of entries, so that --cat finds them without reading the whole archive")
+        )
+        .arg(
+            Arg::from_usage("--no-metadata")
+                .help("When decompressing, doesn't restore modification times stored in the archive")
+        )
+        .arg(
+            Arg::from_usage("--permissions")
+                .conflicts_with("no-metadata")
+                .help("When decompressing, restores permissions stored in the archive instead of leaving them to umask")
+        )
+        .arg(
+            Arg::from_usage("--stdin-name [name]")
+                .help("When compressing, name of the entry read from standard input, given as '-'. Defaults to 'stdin'.")
+        )
+        .arg(
+            Arg::from_usage("-r --recursive")
+                .help("When compressing, add contents of directories too. As archives are flat, see --flatten for naming of entries.")
+        )
+        .arg(
+            Arg::from_usage("--flatten [strategy]")
+                .possible_values(&["basename", "path"])
+                .requires("recursive")
+                .help("Names entries by filename only, or by the whole path joined by --separator. Defaults to basename.")
+        )
+        .arg(
+            Arg::from_usage("--separator [separator]")
+                .requires("recursive")
+                .help("Replacement of path separators for '--flatten path'. Defaults to '__'.")
+        )
+        .arg(
+            Arg::from_usage("--paths")
+                .help("Uses paths extension of the format. When compressing recursively, stores relative paths such as 'src/main.rs'. When decompressing, recreates the directories.")
+        )
+        .arg(
+            Arg::from_usage("--include [pattern]")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("recursive")
+                .help("When compressing recursively, add only files whose path matches wildcard pattern. Can be repeated.")
+        )
+        .arg(
+            Arg::from_usage("--exclude [pattern]")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("recursive")
+                .help("When compressing recursively, leave out files and directories whose path matches wildcard pattern. Can be repeated.")
+        )
+        .arg(
+            Arg::from_usage("[files]...")
+                .help("If decompressing or listing, should be ONLY name of the archive. If compressing, should be list of files, optionally as SRC=NAME to store file SRC under name NAME. Existing files are taken whole, otherwise name is separated by the last '=' and trailing '=' means no name.")
         )
         .arg(
             Arg::from_usage("-o --output")
                 .takes_value(true)
-                .help("Name of archive while compressing OR output directory while decompressing.")
+                .help("Name of archive while compressing OR output directory while decompressing. Archive named '-' is written to standard output.")
         )
         .group(
             ArgGroup::with_name("modes")
                 .multiple(false)
-                .args(&["compress", "decompress", "list"])
+                .args(&["compress", "decompress", "append", "delete", "rename", "cat", "test", "list"])
+        )
+        .group(
+            ArgGroup::with_name("overwriting")
+                .multiple(false)
+                .args(&["overwrite", "no-clobber", "rename-existing", "force"])
         )
         .group(
             ArgGroup::with_name("sorting")
@@ -83,6 +241,11 @@ fn parse_args() -> clap::ArgMatches<'static> {
 enum Mode {
     Compress,
     Decompress,
+    Append,
+    Delete,
+    Rename,
+    Cat,
+    Test,
     Listing,
 }
 
@@ -92,9 +255,78 @@ struct Options {
     work_directory: Option<PathBuf>,
     archive_name: Option<PathBuf>,
     files: Vec<PathBuf>,
+    entries: Vec<String>,
+    renames: Vec<(String, String)>,
+    selection: select::Selection,
+    index: Option<usize>,
+    salvage: bool,
+    unsafe_names: decompress::UnsafeNamePolicy,
+    overwrite: decompress::OverwritePolicy,
+    duplicates: decompress::DuplicatePolicy,
+    fsync: bool,
+    no_clobber: bool,
+    walk: Option<walk::Walk>,
+    paths: bool,
+    stdin_name: String,
+    manifest: Option<PathBuf>,
+    files_from: Option<PathBuf>,
+    null: bool,
+    entry_options: compress::EntryOptions,
+    metadata: bool,
+    permissions: bool,
     sorting: ListingSort,
 }
 
+/// Reads options of files to add into archive, shared by compression and appending.
+/// Returns false if they're invalid or there are no files to add
+fn select_sources(args: &clap::ArgMatches<'_>, opts: &mut Options) -> bool {
+    opts.manifest = args.value_of_os("manifest").map(PathBuf::from);
+    if opts.files.is_empty() && opts.manifest.is_none() && opts.files_from.is_none() {
+        return false;
+    }
+    opts.paths = args.is_present("paths");
+    let duplicates = match args.value_of("duplicates") {
+        Some("reject") => compress::EntryDuplicatePolicy::Reject,
+        Some("rename") => compress::EntryDuplicatePolicy::Rename,
+        Some("warn") | None => compress::EntryDuplicatePolicy::Warn,
+        Some(_) => return false,
+    };
+    opts.entry_options = compress::EntryOptions::new()
+        .duplicates(duplicates)
+        .compression(match args.value_of("compression") {
+            Some("deflate") => codec::EntryCodec::Deflate,
+            Some("zstd") => codec::EntryCodec::Zstd,
+            _ => codec::EntryCodec::Stored,
+        })
+        .metadata(args.is_present("metadata"))
+        .checksum(
+            args.value_of("checksum")
+                .and_then(checksum::Checksum::from_name),
+        )
+        .index(args.is_present("indexed"));
+    if args.is_present("recursive") {
+        let mut walk = walk::Walk::new();
+        if opts.paths {
+            walk = walk.flatten(walk::Flatten::Paths);
+        } else if args.value_of("flatten") == Some("path") {
+            let separator = args.value_of("separator").unwrap_or("__");
+            walk = walk.flatten(walk::Flatten::Join(separator.to_owned()));
+        }
+        for pattern in args.values_of("include").unwrap_or_default() {
+            walk = walk.include(pattern);
+        }
+        for pattern in args.values_of("exclude").unwrap_or_default() {
+            walk = walk.exclude(pattern);
+        }
+        opts.walk = Some(walk);
+    }
+    opts.stdin_name = args
+        .value_of("stdin-name")
+        .unwrap_or(compress::DEFAULT_STDIN_NAME)
+        .to_owned();
+    true
+}
+
 /// Deduces mode of operation and validates correct arguments for it
 fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
     let mut opts = Options::default();
@@ -111,17 +343,22 @@ fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
         opts.mode = Some(Mode::Compress);
     } else if args.is_present("decompress") {
         opts.mode = Some(Mode::Decompress);
+    } else if args.is_present("append") {
+        opts.mode = Some(Mode::Append);
+    } else if args.is_present("delete") {
+        opts.mode = Some(Mode::Delete);
+    } else if args.is_present("rename") {
+        opts.mode = Some(Mode::Rename);
+    } else if args.is_present("cat") {
+        opts.mode = Some(Mode::Cat);
+    } else if args.is_present("test") {
+        opts.mode = Some(Mode::Test);
     } else if args.is_present("list") {
         opts.mode = Some(Mode::Listing);
     }
     // Auto detection
     else {
-        if opts.files.len() == 1
-            && opts.files[0]
-                .extension()
-                .map(|ext| ext == OsStr::new("dca"))
-                .unwrap_or(false)
-        {
+        if opts.files.len() == 1 && codec::Codec::is_archive_path(&opts.files[0]) {
             opts.mode = Some(Mode::Decompress);
         } else {
             opts.mode = Some(Mode::Compress);
@@ -130,14 +367,31 @@ fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
 
     match opts.mode {
         Some(Mode::Compress) => {
+            // Automatically chosen name shouldn't replace anything unless asked to
+            opts.no_clobber =
+                args.is_present("no-clobber") || (output.is_none() && !args.is_present("force"));
+            opts.files_from = args.value_of_os("files-from").map(PathBuf::from);
+            opts.null = args.is_present("null");
+            if !select_sources(args, &mut opts) {
+                opts.mode = None;
+                return opts;
+            }
             opts.archive_name = output;
             match opts.archive_name {
                 None => {
                     opts.archive_name = Some({
                         if opts.files.len() == 1 {
                             let mut buf = OsString::new();
-                            if let Some(file_name) = opts.files[0].file_name() {
-                                buf.push(file_name);
+                            match manifest::parse_source(opts.files[0].as_os_str()) {
+                                (_, Some(name)) => buf.push(name),
+                                (path, None) if path == Path::new(compress::STDIN_PATH) => {
+                                    buf.push(&opts.stdin_name)
+                                }
+                                (path, None) => {
+                                    if let Some(file_name) = path.file_name() {
+                                        buf.push(file_name);
+                                    }
+                                }
                             }
                             buf.push(".dca");
                             PathBuf::from(buf)
@@ -147,7 +401,7 @@ fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
                     });
                 }
                 Some(ref mut name) => {
-                    if name.extension().is_none() {
+                    if name.extension().is_none() && !is_stdio(name) {
                         name.set_extension("dca");
                     }
                 }
@@ -160,6 +414,116 @@ fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
             }
 
             opts.work_directory = output.or_else(|| Some(PathBuf::from(".")));
+            opts.salvage = args.is_present("salvage");
+            opts.unsafe_names = match args.value_of("unsafe-names") {
+                Some("sanitize") => decompress::UnsafeNamePolicy::Sanitize,
+                Some("skip") => decompress::UnsafeNamePolicy::Skip,
+                _ => decompress::UnsafeNamePolicy::Reject,
+            };
+            opts.overwrite = if args.is_present("no-clobber") {
+                decompress::OverwritePolicy::Skip
+            } else if args.is_present("rename-existing") {
+                decompress::OverwritePolicy::Rename
+            } else {
+                decompress::OverwritePolicy::Overwrite
+            };
+            opts.fsync = args.is_present("fsync");
+            opts.metadata = !args.is_present("no-metadata");
+            opts.permissions = args.is_present("permissions");
+            opts.paths = args.is_present("paths");
+            opts.duplicates = match args.value_of("duplicates") {
+                Some("first") => decompress::DuplicatePolicy::KeepFirst,
+                Some("number") => decompress::DuplicatePolicy::Number,
+                Some("last") | None => decompress::DuplicatePolicy::KeepLast,
+                Some(_) => {
+                    opts.mode = None;
+                    return opts;
+                }
+            };
+            for pattern in args.values_of("only").unwrap_or_default() {
+                opts.selection = std::mem::take(&mut opts.selection).glob(pattern);
+            }
+            for name in args.values_of("only-name").unwrap_or_default() {
+                opts.selection = std::mem::take(&mut opts.selection).name(name);
+            }
+            opts.archive_name = std::mem::take(&mut opts.files).into_iter().next();
+        }
+        Some(Mode::Append) => {
+            if opts.files.is_empty() || output.is_some() {
+                opts.mode = None;
+                return opts;
+            }
+
+            opts.archive_name = Some(opts.files.remove(0));
+            if !select_sources(args, &mut opts) {
+                opts.mode = None;
+                return opts;
+            }
+        }
+        Some(Mode::Delete) | Some(Mode::Rename) => {
+            if opts.files.len() < 2 || output.is_some() {
+                opts.mode = None;
+                return opts;
+            }
+
+            opts.archive_name = Some(opts.files.remove(0));
+            let entries: Option<Vec<String>> = std::mem::take(&mut opts.files)
+                .into_iter()
+                .map(|entry| entry.into_os_string().into_string().ok())
+                .collect();
+            let entries = match entries {
+                Some(entries) => entries,
+                None => {
+                    opts.mode = None;
+                    return opts;
+                }
+            };
+            if let Some(Mode::Rename) = opts.mode {
+                let renames: Option<Vec<(String, String)>> = entries
+                    .iter()
+                    .map(|rename| {
+                        let (old, new) = rename.rsplit_once('=')?;
+                        Some((old.to_owned(), new.to_owned()))
+                    })
+                    .collect();
+                match renames {
+                    Some(renames) => opts.renames = renames,
+                    None => opts.mode = None,
+                }
+            } else {
+                opts.entries = entries;
+            }
+        }
+        Some(Mode::Cat) => {
+            if opts.files.len() != 2 || output.is_some() {
+                opts.mode = None;
+                return opts;
+            }
+            if let Some(index) = args.value_of("index") {
+                match index.parse() {
+                    Ok(index) => opts.index = Some(index),
+                    Err(_) => {
+                        opts.mode = None;
+                        return opts;
+                    }
+                }
+            }
+
+            opts.archive_name = Some(opts.files.remove(0));
+            match std::mem::take(&mut opts.files)
+                .pop()
+                .and_then(|name| name.into_os_string().into_string().ok())
+            {
+                Some(name) => opts.entries = vec![name],
+                None => opts.mode = None,
+            }
+        }
+        Some(Mode::Test) => {
+            if opts.files.len() != 1 || output.is_some() {
+                opts.mode = None;
+                return opts;
+            }
+
             opts.archive_name = std::mem::take(&mut opts.files).into_iter().next();
         }
         Some(Mode::Listing) => {
@@ -180,6 +544,104 @@ fn select_mode(args: &clap::ArgMatches<'_>) -> Options {
     opts
 }
 
+/// Checks whether path stands for standard input or output
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new(compress::STDIN_PATH)
+}
+
+/// Creates the archive, or writes it to standard output if named `-`
+fn compress_archive(
+    archive_name: &Path,
+    fhandler: &mut impl compress::FileHandler,
+    no_clobber: bool,
+) -> error::Result<()> {
+    if !is_stdio(archive_name) {
+        return compress::compress_files_with(archive_name, fhandler, no_clobber);
+    }
+    let mut ehandler = compress::DefaultErrorHandler::new(archive_name);
+    let stdout = io::stdout();
+    let mut writer = io::BufWriter::new(stdout.lock());
+    compress::compress_into(&mut writer, fhandler, &mut ehandler)
+        .and_then(|()| writer.flush().map_err(error::ArchiveError::ArchiveIo))
+        .inspect_err(|e| ehandler.on_fatal(e))
+}
+
+/// Creates the archive as [`compress_archive`] does, or appends to existing one if `append` is set
+fn write_archive(
+    archive_name: &Path,
+    fhandler: &mut impl compress::FileHandler,
+    no_clobber: bool,
+    append: bool,
+) -> error::Result<()> {
+    if append {
+        compress::append_files_with(archive_name, fhandler)
+    } else {
+        compress_archive(archive_name, fhandler, no_clobber)
+    }
+}
+
+/// Exits with hint if compression failed because the archive already exists, which the error handler reported
+fn report_existing(err: &error::ArchiveError) {
+    if let error::ArchiveError::FileExists(_) = err {
+        eprintln!("Use --force to replace it.");
+        exit(1);
+    }
+}
+
+/// Extracts the archive, or reads it from standard input if named `-`
+fn decompress_archive(
+    archive_name: &Path,
+    fhandler: &mut impl decompress::FileHandler,
+) -> error::Result<()> {
+    if !is_stdio(archive_name) {
+        return decompress::decompress_files_with(archive_name, fhandler);
+    }
+    let ehandler = decompress::DefaultErrorHandler::new(archive_name);
+    decompress::decompress_from(&mut io::stdin().lock(), fhandler, &ehandler)
+        .inspect_err(|e| ehandler.on_fatal(e))
+}
+
+/// Extracts damaged archive, reporting unusable parts
+fn salvage_archive(archive_name: &Path, fhandler: &mut impl decompress::FileHandler) {
+    let ehandler = decompress::DefaultErrorHandler::new(archive_name);
+    let mut salvage = || {
+        let arch = File::open(archive_name).map_err(error::ArchiveError::ArchiveIo)?;
+        salvage::salvage_from(&mut io::BufReader::new(arch), fhandler, &ehandler)
+    };
+    match salvage() {
+        Ok(report) => {
+            for name in &report.partial {
+                eprintln!("Entry {:?} is incomplete.", name);
+            }
+            for range in &report.damaged {
+                if range.is_empty() {
+                    eprintln!("Archive ends at byte {} without final footer.", range.start);
+                } else {
+                    eprintln!(
+                        "Bytes {}..{} of the archive could not be used.",
+                        range.start, range.end
+                    );
+                }
+            }
+            if !report.is_clean() {
+                eprintln!(
+                    "Archive {:?} is damaged, {} entries were recovered.",
+                    archive_name, report.entries
+                );
+                exit(2);
+            }
+        }
+        Err(err) => {
+            eprintln!(
+                "Salvaging archive {:?} failed: {}",
+                archive_name,
+                ErrChain(&err)
+            );
+            exit(1);
+        }
+    }
+}
+
 fn main() {
     env_logger::init();
     let args = parse_args();
@@ -188,16 +650,125 @@ fn main() {
     debug!("Collected options: {:?}", opts);
     match opts {
         Options {
-            mode: Some(Mode::Compress),
+            mode: Some(mode @ (Mode::Compress | Mode::Append)),
             files,
             archive_name: Some(archive_name),
+            no_clobber,
+            walk,
+            paths,
+            stdin_name,
+            manifest,
+            files_from,
+            null,
+            entry_options,
             ..
         } => {
-            if let Err(err) = compress_files(&files, &archive_name) {
-                eprintln!(
-                    "Compression failed.\nArchive filename: {:?}\nArchive contents: {:?}\nProblem: {}",
-                    archive_name, files, ErrChain(&err)
+            if let Some(list) = files_from {
+                let reader: Box<dyn io::BufRead> = if list == Path::new(compress::STDIN_PATH) {
+                    Box::new(io::stdin().lock())
+                } else {
+                    match File::open(&list) {
+                        Ok(file) => Box::new(io::BufReader::new(file)),
+                        Err(err) => {
+                            eprintln!("Opening list of files {:?} failed: {}", list, err);
+                            exit(1);
+                        }
+                    }
+                };
+                let res = compress_archive(
+                    &archive_name,
+                    &mut compress::FileListHandler::new(reader, &list, null)
+                        .options(entry_options)
+                        .stdin_name(stdin_name),
+                    no_clobber,
                 );
+                if let Err(err) = res {
+                    report_existing(&err);
+                    eprintln!(
+                        "Compression failed.\nArchive filename: {:?}\nList of files: {:?}\nProblem: {}",
+                        archive_name, list, ErrChain(&err)
+                    );
+                    exit(1);
+                }
+                return;
+            }
+            let append = matches!(mode, Mode::Append);
+            let mut sources: Vec<manifest::Source> = files
+                .iter()
+                .map(|file| manifest::parse_source(file.as_os_str()))
+                .collect();
+            if let Some(manifest) = manifest {
+                match manifest::read_manifest_file(&manifest) {
+                    Ok(listed) => sources.extend(listed),
+                    Err(err) => {
+                        eprintln!("Reading manifest {:?} failed: {}", manifest, err);
+                        exit(1);
+                    }
+                }
+            }
+            let named = sources.iter().any(|(_, name)| name.is_some());
+
+            let res = match walk {
+                Some(_) if named => {
+                    eprintln!("Stored names can't be used with --recursive.");
+                    exit(1);
+                }
+                Some(walk) => {
+                    walk.collect(sources.iter().map(|(path, _)| path))
+                        .and_then(|files| {
+                            write_archive(
+                                &archive_name,
+                                &mut compress::NamedFileHandler::new(files)
+                                    .options(entry_options)
+                                    .paths(paths),
+                                no_clobber,
+                                append,
+                            )
+                        })
+                }
+                None if named => sources
+                    .into_iter()
+                    .map(|(path, name)| {
+                        let name = match name {
+                            Some(name) => name,
+                            None if path == Path::new(compress::STDIN_PATH) => stdin_name.clone(),
+                            None => writer::entry_name(&path)?.to_owned(),
+                        };
+                        Ok((path, name))
+                    })
+                    .collect::<error::Result<Vec<_>>>()
+                    .and_then(|files| {
+                        write_archive(
+                            &archive_name,
+                            &mut compress::NamedFileHandler::new(files)
+                                .options(entry_options)
+                                .paths(paths),
+                            no_clobber,
+                            append,
+                        )
+                    }),
+                None => write_archive(
+                    &archive_name,
+                    &mut compress::DefaultFileHandler::new(sources.iter().map(|(path, _)| path))
+                        .options(entry_options)
+                        .stdin_name(stdin_name),
+                    no_clobber,
+                    append,
+                ),
+            };
+            if let Err(err) = res {
+                report_existing(&err);
+                if append {
+                    eprintln!(
+                        "Appending to archive failed, archive was left unchanged.\nArchive filename: {:?}\nAppended files: {:?}\nProblem: {}",
+                        archive_name, files, ErrChain(&err)
+                    );
+                } else {
+                    eprintln!(
+                        "Compression failed.\nArchive filename: {:?}\nArchive contents: {:?}\nProblem: {}",
+                        archive_name, files, ErrChain(&err)
+                    );
+                }
                 exit(1);
             }
         }
@@ -205,9 +776,35 @@ fn main() {
             mode: Some(Mode::Decompress),
             archive_name: Some(archive_name),
             work_directory: Some(work_directory),
+            selection,
+            salvage,
+            unsafe_names,
+            overwrite,
+            duplicates,
+            fsync,
+            paths,
+            metadata,
+            permissions,
             ..
         } => {
-            if let Err(err) = decompress_files(&archive_name, &work_directory) {
+            let mut fhandler = decompress::FilterFileHandler::new(
+                decompress::DefaultFileHandler::new(&work_directory)
+                    .unsafe_names(unsafe_names)
+                    .overwrite(overwrite)
+                    .duplicates(duplicates)
+                    .fsync(fsync)
+                    .metadata(metadata)
+                    .permissions(permissions)
+                    .paths(paths),
+                |name| selection.matches(name),
+            );
+            if salvage {
+                if is_stdio(&archive_name) {
+                    eprintln!("Salvaging needs seekable archive, standard input can't be used.");
+                    exit(1);
+                }
+                salvage_archive(&archive_name, &mut fhandler);
+            } else if let Err(err) = decompress_archive(&archive_name, &mut fhandler) {
                 eprintln!(
                     "Decompression of archive {:?} failed: {}",
                     archive_name,
@@ -216,6 +813,82 @@ fn main() {
                 exit(1);
             }
         }
+        Options {
+            mode: Some(Mode::Delete),
+            archive_name: Some(archive_name),
+            entries,
+            ..
+        } => {
+            if let Err(err) = edit::delete_entries(&archive_name, &entries) {
+                eprintln!(
+                    "Deleting entries {:?} from archive {:?} failed, archive was left unchanged: {}",
+                    entries,
+                    archive_name,
+                    ErrChain(&err)
+                );
+                exit(1);
+            }
+        }
+        Options {
+            mode: Some(Mode::Rename),
+            archive_name: Some(archive_name),
+            renames,
+            ..
+        } => {
+            if let Err(err) = edit::rename_entries(&archive_name, &renames) {
+                eprintln!(
+                    "Renaming entries of archive {:?} failed, archive was left unchanged: {}",
+                    archive_name,
+                    ErrChain(&err)
+                );
+                exit(1);
+            }
+        }
+        Options {
+            mode: Some(Mode::Cat),
+            archive_name: Some(archive_name),
+            entries,
+            index,
+            ..
+        } => {
+            let cat = || {
+                let arch = File::open(&archive_name).map_err(error::ArchiveError::ArchiveIo)?;
+                let stdout = io::stdout();
+                let mut sink = io::BufWriter::new(stdout.lock());
+                decompress::extract_entry(
+                    &mut io::BufReader::new(arch),
+                    &entries[0],
+                    index,
+                    &mut sink,
+                )
+            };
+            if let Err(err) = cat() {
+                eprintln!(
+                    "Writing entry {:?} of archive {:?} failed: {}",
+                    entries[0],
+                    archive_name,
+                    ErrChain(&err)
+                );
+                exit(1);
+            }
+        }
+        Options {
+            mode: Some(Mode::Test),
+            archive_name: Some(archive_name),
+            ..
+        } => {
+            let test = || {
+                let arch = File::open(&archive_name).map_err(error::ArchiveError::ArchiveIo)?;
+                decompress::verify(&mut io::BufReader::new(arch))
+            };
+            match test() {
+                Ok(count) => println!("Archive {:?} is valid, {} entries.", archive_name, count),
+                Err(err) => {
+                    eprintln!("Archive {:?} is broken: {}", archive_name, ErrChain(&err));
+                    exit(1);
+                }
+            }
+        }
         Options {
             mode: Some(Mode::Listing),
             archive_name: Some(archive_name),
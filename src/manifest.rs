@@ -0,0 +1,107 @@
+//! Implements `SRC=NAME` syntax of files to compress, used on command line and in manifest files
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use dca::compress::STDIN_PATH;
+
+/// File to compress along with name under which it should be stored, if it differs from its filename
+pub type Source = (PathBuf, Option<String>);
+
+/// Splits `SRC=NAME` into source path and stored name
+///
+/// Arguments naming existing files are taken whole, so `key=value.txt` is just a path if such file exists.
+/// Otherwise the last `=` separates the name, so `a=b=c` stores `a=b` as `c`. Empty name means there's none,
+/// so paths containing `=` that don't exist yet, such as `-` piped from another command, are given with trailing `=`.
+/// Plain `SRC` without `=` and arguments that aren't valid UTF-8 have no stored name.
+pub fn parse_source(arg: &OsStr) -> Source {
+    parse_source_in(arg, Path::new(""))
+}
+
+/// Variant of [`parse_source`] checking for existing files relative to `base`
+fn parse_source_in(arg: &OsStr, base: &Path) -> Source {
+    if fs::symlink_metadata(base.join(arg)).is_ok() {
+        return (arg.into(), None);
+    }
+    match arg.to_str().and_then(|arg| arg.rsplit_once('=')) {
+        Some((path, "")) => (path.into(), None),
+        Some((path, name)) => (path.into(), Some(name.to_owned())),
+        None => (arg.into(), None),
+    }
+}
+
+/// Reads list of sources in `SRC=NAME` format (see [`parse_source`]), one per line. Empty lines are skipped.
+///
+/// Relative paths are resolved against `base`, except for [`STDIN_PATH`].
+pub fn read_manifest(reader: impl BufRead, base: &Path) -> io::Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (mut source, name) = parse_source_in(OsStr::new(&line), base);
+        if source.is_relative() && source != Path::new(STDIN_PATH) {
+            source = base.join(source);
+        }
+        sources.push((source, name));
+    }
+    Ok(sources)
+}
+
+/// Opens and reads manifest file, see [`read_manifest`]
+///
+/// Relative paths are resolved against the directory of the manifest.
+pub fn read_manifest_file(path: &Path) -> io::Result<Vec<Source>> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    read_manifest(io::BufReader::new(fs::File::open(path)?), base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_fs::{prelude::*, TempDir};
+
+    #[test]
+    fn test_parse() {
+        let manifest = "build/out/app.log=app-2024.log\n\nnotes.txt\na=b=c\nkey=value=\n";
+        let sources = read_manifest(manifest.as_bytes(), Path::new("")).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                (
+                    PathBuf::from("build/out/app.log"),
+                    Some("app-2024.log".to_owned())
+                ),
+                (PathBuf::from("notes.txt"), None),
+                (PathBuf::from("a=b"), Some("c".to_owned())),
+                (PathBuf::from("key=value"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_manifest_file() {
+        let dir = TempDir::new().unwrap();
+        dir.child("lists/files.txt")
+            .write_str("notes.txt=renamed.txt\n/abs/path\n-\nk=v.txt\n")
+            .unwrap();
+        dir.child("lists/k=v.txt").touch().unwrap();
+        let sources = read_manifest_file(dir.child("lists/files.txt").path()).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                (
+                    dir.child("lists/notes.txt").to_path_buf(),
+                    Some("renamed.txt".to_owned())
+                ),
+                (PathBuf::from("/abs/path"), None),
+                (PathBuf::from(STDIN_PATH), None),
+                (dir.child("lists/k=v.txt").to_path_buf(), None),
+            ]
+        );
+    }
+}
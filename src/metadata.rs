@@ -0,0 +1,138 @@
+//! Metadata of files stored in attributes of extended format entries
+//!
+//! See project's README for the list of attributes.
+
+use std::fs::{self, File};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata of single entry. Every item is optional, as archives may carry any subset of them
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Metadata {
+    /// Modification time, stored with precision of seconds
+    pub mtime: Option<SystemTime>,
+    /// Unix permission bits, such as `0o644`
+    pub mode: Option<u32>,
+    /// MIME type of the content, such as `text/plain`
+    pub content_type: Option<String>,
+}
+
+impl Metadata {
+    /// Collects modification time and (on Unix) permissions of a file
+    pub fn from_fs(meta: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(meta.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        Self {
+            mtime: meta.modified().ok(),
+            mode,
+            content_type: None,
+        }
+    }
+
+    /// Returns true if no metadata is known
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Reads metadata from entry attributes. Values that can't be parsed are ignored
+    pub fn from_attributes(attributes: &[(String, String)]) -> Self {
+        let mut metadata = Self::default();
+        for (key, value) in attributes {
+            match key.as_str() {
+                "mtime" => metadata.mtime = value.parse::<i64>().ok().map(from_timestamp),
+                "mode" => metadata.mode = u32::from_str_radix(value, 8).ok(),
+                "content-type" => metadata.content_type = Some(value.clone()),
+                _ => (),
+            }
+        }
+        metadata
+    }
+
+    /// Converts metadata into entry attributes
+    pub fn to_attributes(&self) -> Vec<(String, String)> {
+        let mut attributes = Vec::new();
+        if let Some(mtime) = self.mtime {
+            attributes.push(("mtime".to_owned(), to_timestamp(mtime).to_string()));
+        }
+        if let Some(mode) = self.mode {
+            attributes.push(("mode".to_owned(), format!("{:o}", mode)));
+        }
+        if let Some(content_type) = &self.content_type {
+            if !content_type.contains('\n') {
+                attributes.push(("content-type".to_owned(), content_type.clone()));
+            }
+        }
+        attributes
+    }
+
+    /// Sets modification time and (on Unix) permissions of the file. Only permission bits
+    /// (`0o777`) are restored, special bits such as setuid are left out.
+    pub fn apply(&self, file: &File) -> io::Result<()> {
+        if let Some(mtime) = self.mtime {
+            file.set_modified(mtime)?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts time into whole seconds since Unix epoch
+fn to_timestamp(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+/// Converts seconds since Unix epoch into time
+fn from_timestamp(secs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes() {
+        let metadata = Metadata {
+            mtime: Some(from_timestamp(1_700_000_000)),
+            mode: Some(0o644),
+            content_type: Some("text/plain".to_owned()),
+        };
+        let attributes = metadata.to_attributes();
+        assert_eq!(
+            attributes,
+            [
+                ("mtime".to_owned(), "1700000000".to_owned()),
+                ("mode".to_owned(), "644".to_owned()),
+                ("content-type".to_owned(), "text/plain".to_owned()),
+            ]
+        );
+        assert_eq!(Metadata::from_attributes(&attributes), metadata);
+
+        let attributes = [
+            ("mtime".to_owned(), "-5".to_owned()),
+            ("mode".to_owned(), "9".to_owned()),
+            ("codec".to_owned(), "zstd".to_owned()),
+        ];
+        let metadata = Metadata::from_attributes(&attributes);
+        assert_eq!(metadata.mtime, Some(UNIX_EPOCH - Duration::from_secs(5)));
+        assert_eq!(metadata.mode, None);
+        assert!(Metadata::from_attributes(&[]).is_empty());
+    }
+}
@@ -0,0 +1,637 @@
+//! Iterator-style access to contents of DCA archives
+
+use std::cell::{RefCell, RefMut};
+use std::cmp::min;
+use std::convert::TryFrom;
+use std::io::{self, prelude::*, BufRead, Seek, SeekFrom};
+
+use crate::error::{ArchiveError, DecompressionError, FilePosition, Result};
+
+use ArchiveError as E;
+
+/// Returns true if read from the reader matches fixed byte sequence
+fn read_matches<const N: usize>(
+    reader: &mut impl Read,
+    reference: &'static [u8; N],
+    position: &mut FilePosition,
+) -> Result<bool, io::Error> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+
+    let res = &buf == reference;
+    if res {
+        *position += N as FilePosition;
+    }
+    Ok(res)
+}
+
+/// Reads UTF-8 text from the archive up to the following newline (\n) character, and transforms
+/// the read string into appropriate result type via `processor` callback.
+///
+/// Also allows to read nothing, returning None in that case.
+fn read_line<T>(
+    reader: &mut impl BufRead,
+    line_buf: &mut String,
+    position: &mut FilePosition,
+    processor: impl FnOnce(&str) -> Result<T>,
+) -> Result<Option<T>> {
+    line_buf.truncate(0);
+    reader.read_line(line_buf).map_err(E::ArchiveIo)?;
+    if line_buf.is_empty() {
+        return Ok(None);
+    }
+    let res = processor(&line_buf[..line_buf.len() - 1]);
+    if res.is_ok() {
+        *position += line_buf.len() as FilePosition;
+    }
+    res.map(Some)
+}
+
+/// Reads file size segment from the archive
+fn read_file_size(
+    reader: &mut impl BufRead,
+    line_buf: &mut String,
+    position: &mut FilePosition,
+) -> Result<FilePosition> {
+    let old_pos = *position;
+    let handler = |s: &str| {
+        s.parse::<FilePosition>().map_err(|_| E::CorruptedArchive {
+            position: old_pos,
+            section: DecompressionError::FileSize,
+        })
+    };
+    read_line(reader, line_buf, position, handler).and_then(|val| match val {
+        Some(size) => Ok(size),
+        None => Err(E::CorruptedArchive {
+            position: *position,
+            section: DecompressionError::FileSize,
+        }),
+    })
+}
+
+/// Reads header of the archive, returning true if it's in extended format
+fn read_header(reader: &mut impl BufRead, position: &mut FilePosition) -> Result<bool> {
+    let corrupted = |position| E::CorruptedArchive {
+        position,
+        section: DecompressionError::Header,
+    };
+    if !read_matches(reader, b"DCA", position).map_err(E::ArchiveIo)? {
+        return Err(corrupted(*position));
+    }
+    let mut version = [0u8];
+    reader.read_exact(&mut version).map_err(E::ArchiveIo)?;
+    match &version {
+        b"\n" => {
+            *position += 1;
+            return Ok(false);
+        }
+        b"2" => (),
+        _ => return Err(corrupted(*position)),
+    }
+    *position += 1;
+    if read_matches(reader, b"\n", position).map_err(E::ArchiveIo)? {
+        Ok(true)
+    } else {
+        Err(corrupted(*position - 1))
+    }
+}
+
+/// Returns true if `key` can be used as attribute key of the extended format
+pub(crate) fn is_attribute_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// Line following filename in extended format
+enum EntryLine {
+    Attribute(String, String),
+    Size(FilePosition),
+}
+
+/// Reads attribute lines of extended format entry, followed by file size
+fn read_attributes(
+    reader: &mut impl BufRead,
+    line_buf: &mut String,
+    position: &mut FilePosition,
+    attributes: &mut Vec<(String, String)>,
+) -> Result<FilePosition> {
+    loop {
+        let old_pos = *position;
+        let handler =
+            |s: &str| match s.split_once('=') {
+                Some((key, value)) if is_attribute_key(key) => {
+                    if key == "size" && value.parse::<FilePosition>().is_err() {
+                        return Err(E::CorruptedArchive {
+                            position: old_pos,
+                            section: DecompressionError::Attribute,
+                        });
+                    }
+                    Ok(EntryLine::Attribute(key.to_owned(), value.to_owned()))
+                }
+                Some(_) => Err(E::CorruptedArchive {
+                    position: old_pos,
+                    section: DecompressionError::Attribute,
+                }),
+                None => s.parse::<FilePosition>().map(EntryLine::Size).map_err(|_| {
+                    E::CorruptedArchive {
+                        position: old_pos,
+                        section: DecompressionError::FileSize,
+                    }
+                }),
+            };
+        match read_line(reader, line_buf, position, handler)? {
+            Some(EntryLine::Attribute(key, value)) => attributes.push((key, value)),
+            Some(EntryLine::Size(size)) => return Ok(size),
+            None => {
+                return Err(E::CorruptedArchive {
+                    position: *position,
+                    section: DecompressionError::FileSize,
+                })
+            }
+        }
+    }
+}
+
+/// Skips over `count` bytes by reading and discarding them
+fn skip_by_reading<R: BufRead>(reader: &mut R, count: FilePosition) -> io::Result<FilePosition> {
+    io::copy(&mut reader.take(count), &mut io::sink())
+}
+
+/// Skips over `count` bytes by seeking. Seeking past the end is not detected.
+fn skip_by_seeking<R: Seek>(reader: &mut R, count: FilePosition) -> io::Result<FilePosition> {
+    let offset = i64::try_from(count).map_err(|_| io::ErrorKind::InvalidInput)?;
+    reader.seek(SeekFrom::Current(offset))?;
+    Ok(count)
+}
+
+/// Progress of parsing the archive
+enum Stage {
+    /// Header not yet processed
+    Start,
+    /// Between entries. If previous entry exists, its payload ends at given position
+    Entries(Option<FilePosition>),
+    /// No more entries can be read, either due to end of archive or due to previous error
+    Done,
+}
+
+/// Shared state of [`ArchiveReader`] and its [`Entry`] values
+struct State<R> {
+    reader: R,
+    /// Position in the archive. Relative to the point where reading started, unless reader is seekable
+    position: FilePosition,
+    skip: fn(&mut R, FilePosition) -> io::Result<FilePosition>,
+    stage: Stage,
+    /// Set once the header shows the archive is in extended format
+    extended: bool,
+    line_buf: String,
+}
+
+/// Reader of DCA archives, providing its entries through an iterator
+///
+/// Unlike callback-based [`crate::decompress::decompress_from`], this allows plain control flow
+/// (early exits, `?` operator) when processing the archive.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{self, BufReader};
+/// use dca::reader::ArchiveReader;
+///
+/// # fn main() -> dca::error::Result<()> {
+/// let file = File::open("archive.dca").map_err(dca::error::ArchiveError::ArchiveIo)?;
+/// let mut archive = ArchiveReader::new_seekable(BufReader::new(file))?;
+/// for entry in archive.entries() {
+///     let mut entry = entry?;
+///     if entry.name() == "notes.txt" {
+///         io::copy(&mut entry, &mut io::stdout()).map_err(dca::error::ArchiveError::ArchiveIo)?;
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ArchiveReader<R> {
+    state: RefCell<State<R>>,
+}
+
+impl<R: BufRead> ArchiveReader<R> {
+    /// Creates reader processing the archive strictly forward
+    ///
+    /// Unread parts of payloads are read and discarded, so pipes, sockets or standard input can be used.
+    /// Positions are relative to the point where the reader started.
+    pub fn new(reader: R) -> Self {
+        Self::with_skip(reader, 0, skip_by_reading::<R>)
+    }
+
+    fn with_skip(
+        reader: R,
+        position: FilePosition,
+        skip: fn(&mut R, FilePosition) -> io::Result<FilePosition>,
+    ) -> Self {
+        Self {
+            state: RefCell::new(State {
+                reader,
+                position,
+                skip,
+                stage: Stage::Start,
+                extended: false,
+                line_buf: String::new(),
+            }),
+        }
+    }
+
+    /// Iterator over entries of the archive
+    ///
+    /// Only one entry can be used at a time - it should be dropped before requesting the next one,
+    /// otherwise the iterator fails with [`ArchiveError::ArchiveIo`].
+    /// Payload not read from the entry is skipped.
+    ///
+    /// Once an error is returned, the iterator ends.
+    pub fn entries(&mut self) -> Entries<'_, R> {
+        Entries { state: &self.state }
+    }
+
+    /// Current position in the archive
+    pub fn position(&self) -> FilePosition {
+        self.state.borrow().position
+    }
+
+    /// Returns true if the archive is in extended format, whose entries may carry attributes.
+    /// Known only after the first entry was requested.
+    pub fn is_extended(&self) -> bool {
+        self.state.borrow().extended
+    }
+
+    /// Unwraps the underlying reader
+    pub fn into_inner(self) -> R {
+        self.state.into_inner().reader
+    }
+}
+
+impl<R: BufRead + Seek> ArchiveReader<R> {
+    /// Creates reader that seeks over unread parts of payloads
+    ///
+    /// Positions are absolute positions in the reader.
+    pub fn new_seekable(mut reader: R) -> Result<Self> {
+        let position = reader.stream_position().map_err(E::ArchiveIo)?;
+        Ok(Self::with_skip(reader, position, skip_by_seeking::<R>))
+    }
+
+    /// Moves to the entry starting at `position`, such as one found by [`crate::index::read_index`],
+    /// so that it's the next one provided by [`Self::entries`]
+    ///
+    /// Header of the archive is read first if it wasn't yet, so the reader has to be created at the start of the archive.
+    pub fn seek_entry(&mut self, position: FilePosition) -> Result<()> {
+        let state = self.state.get_mut();
+        if let Stage::Start = state.stage {
+            state.extended = read_header(&mut state.reader, &mut state.position)?;
+        }
+        state
+            .reader
+            .seek(SeekFrom::Start(position))
+            .map_err(E::ArchiveIo)?;
+        state.position = position;
+        state.stage = Stage::Entries(None);
+        Ok(())
+    }
+}
+
+/// Iterator over archive's entries, see [`ArchiveReader::entries`]
+pub struct Entries<'a, R> {
+    state: &'a RefCell<State<R>>,
+}
+
+impl<'a, R: BufRead> Entries<'a, R> {
+    fn next_entry(state: &mut State<R>) -> Result<Option<EntryHeader>> {
+        let State {
+            reader,
+            position,
+            skip,
+            stage,
+            extended,
+            line_buf,
+        } = state;
+
+        match *stage {
+            Stage::Start => {
+                *extended = read_header(reader, position)?;
+            }
+            Stage::Entries(None) => (),
+            Stage::Entries(Some(payload_end)) => {
+                // This is mildly redundant if user is well behaved and already fully reads up to this point,
+                // but we can't depend on soundness of external code
+                let remaining = payload_end - *position;
+                if remaining > 0 {
+                    let skipped = skip(reader, remaining).map_err(E::ArchiveIo)?;
+                    *position += skipped;
+                    if skipped < remaining {
+                        return Err(E::CorruptedArchive {
+                            position: *position,
+                            section: DecompressionError::Payload,
+                        });
+                    }
+                }
+                // Footer
+                if !read_matches(reader, b"\n", position).map_err(E::ArchiveIo)? {
+                    return Err(ArchiveError::CorruptedArchive {
+                        position: *position,
+                        section: DecompressionError::Footer,
+                    });
+                }
+            }
+            Stage::Done => return Ok(None),
+        }
+        *stage = Stage::Entries(None);
+
+        let start = *position;
+        let fname: String = match read_line(reader, line_buf, position, |s| Ok(s.to_owned()))? {
+            // Final file
+            None => {
+                *stage = Stage::Done;
+                return Ok(None);
+            }
+            Some(fname) => fname,
+        };
+        let mut attributes = Vec::new();
+        let fsize = if *extended {
+            read_attributes(reader, line_buf, position, &mut attributes)?
+        } else {
+            read_file_size(reader, line_buf, position)?
+        };
+        *stage = Stage::Entries(Some(*position + fsize));
+
+        Ok(Some((start, fname, fsize, attributes)))
+    }
+}
+
+/// Position, name, payload size and attributes of an entry
+type EntryHeader = (FilePosition, String, FilePosition, Vec<(String, String)>);
+
+impl<'a, R: BufRead> Iterator for Entries<'a, R> {
+    type Item = Result<Entry<'a, R>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = match self.state.try_borrow_mut() {
+            Ok(state) => state,
+            Err(_) => {
+                return Some(Err(E::ArchiveIo(io::Error::other(
+                    "previous archive entry is still in use",
+                ))))
+            }
+        };
+        match Self::next_entry(&mut state) {
+            Ok(None) => None,
+            Ok(Some((start, name, len, attributes))) => Some(Ok(Entry {
+                start,
+                name,
+                len,
+                attributes,
+                offset: state.position,
+                remaining: len,
+                state,
+            })),
+            Err(err) => {
+                state.stage = Stage::Done;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Finds value of the last attribute called `key`
+pub(crate) fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Size of the original content of entry whose payload has `len` bytes, given by `size` attribute
+pub(crate) fn original_len(attributes: &[(String, String)], len: FilePosition) -> FilePosition {
+    attribute(attributes, "size")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(len)
+}
+
+/// Single archive entry, providing its payload through [`Read`] and [`BufRead`]
+///
+/// Reading is limited to the payload, attempts to read more result in EOF. Payload is provided as stored,
+/// compressed entries of extended format can be decoded with [`crate::codec::EntryCodec::decoder`].
+pub struct Entry<'a, R> {
+    start: FilePosition,
+    name: String,
+    len: FilePosition,
+    attributes: Vec<(String, String)>,
+    offset: FilePosition,
+    remaining: FilePosition,
+    state: RefMut<'a, State<R>>,
+}
+
+impl<'a, R> Entry<'a, R> {
+    /// Archive entry filename. Not validated, may contain arbitrary UTF-8 apart from newline
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Size of the payload in bytes
+    pub fn len(&self) -> FilePosition {
+        self.len
+    }
+    /// Returns true if payload is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Attributes of extended format entry as key-value pairs, in order of appearance
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+    /// Value of the last attribute called `key`
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        attribute(&self.attributes, key)
+    }
+    /// Size of the original content in bytes, which differs from [`Self::len`] for compressed entries
+    pub fn original_len(&self) -> FilePosition {
+        original_len(&self.attributes, self.len)
+    }
+    /// Position of the entry (its filename) in the archive
+    pub fn start(&self) -> FilePosition {
+        self.start
+    }
+    /// Position of the payload in the archive
+    pub fn offset(&self) -> FilePosition {
+        self.offset
+    }
+    /// Current position in the archive, somewhere within the payload
+    pub fn position(&self) -> FilePosition {
+        self.state.position
+    }
+}
+
+impl<'a, R: BufRead> Read for Entry<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = min(self.remaining, buf.len() as FilePosition) as usize;
+        let read = self.state.reader.read(&mut buf[..max])?;
+        self.consume_counted(read);
+        Ok(read)
+    }
+}
+
+impl<'a, R: BufRead> BufRead for Entry<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.remaining == 0 {
+            return Ok(&[]);
+        }
+        let remaining = self.remaining;
+        let buf = self.state.reader.fill_buf()?;
+        let max = min(remaining, buf.len() as FilePosition) as usize;
+        Ok(&buf[..max])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = min(self.remaining, amt as FilePosition) as usize;
+        self.state.reader.consume(amt);
+        self.consume_counted(amt);
+    }
+}
+
+impl<'a, R> Entry<'a, R> {
+    fn consume_counted(&mut self, amt: usize) {
+        self.remaining -= amt as FilePosition;
+        self.state.position += amt as FilePosition;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_entries() {
+        let contents: &[u8] = b"DCA\nhello\n3\n123\nworld\n5\n12345\nempty\n0\n\n";
+        let mut archive = ArchiveReader::new(contents);
+        let mut found = Vec::new();
+        for entry in archive.entries() {
+            let mut entry = entry.unwrap();
+            let mut buf = String::new();
+            // Second entry is left unread
+            if entry.name() != "world" {
+                entry.read_to_string(&mut buf).unwrap();
+            }
+            found.push((entry.name().to_owned(), entry.len(), entry.offset(), buf));
+        }
+        assert_eq!(
+            found,
+            vec![
+                ("hello".to_owned(), 3, 12, "123".to_owned()),
+                ("world".to_owned(), 5, 24, "".to_owned()),
+                ("empty".to_owned(), 0, 38, "".to_owned()),
+            ]
+        );
+        assert_eq!(archive.position(), contents.len() as FilePosition);
+    }
+
+    #[test]
+    fn test_seekable() {
+        let mut contents = Cursor::new(b"xxDCA\nhello\n3\n123\n".to_vec());
+        contents.set_position(2);
+        let mut archive = ArchiveReader::new_seekable(contents).unwrap();
+        let entry = archive.entries().next().unwrap().unwrap();
+        assert_eq!((entry.name(), entry.offset()), ("hello", 14));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut archive = ArchiveReader::new(b"DCA\nfoo\n1000\nbar" as &[u8]);
+        let mut entries = archive.entries();
+        entries.next().unwrap().unwrap();
+        match entries.next() {
+            Some(Err(ArchiveError::CorruptedArchive {
+                position: 16,
+                section: DecompressionError::Payload,
+            })) => (),
+            e => panic!("Unexpected result {:?}", e.map(|e| e.map(|_| ()))),
+        }
+        assert!(entries.next().is_none());
+
+        let mut archive = ArchiveReader::new(b"DCA\nfoo\n3\nbarbaz\n" as &[u8]);
+        let mut entries = archive.entries();
+        let _held = entries.next().unwrap().unwrap();
+        assert!(matches!(
+            entries.next(),
+            Some(Err(ArchiveError::ArchiveIo(_)))
+        ));
+    }
+
+    #[test]
+    fn test_seek_entry() {
+        let contents: &[u8] = b"DCA\nhello\n3\n123\nworld\n5\n12345\n";
+        let mut archive = ArchiveReader::new_seekable(Cursor::new(contents)).unwrap();
+        archive.seek_entry(16).unwrap();
+        let mut entries = archive.entries();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.name(), "world");
+        assert_eq!(entry.offset(), 24);
+        let mut buf = String::new();
+        entry.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "12345");
+        drop(entry);
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_extended() {
+        let contents: &[u8] = b"DCA2\nhello\ncodec=zstd\nsize=10\n3\nabc\nplain\n2\nhi\n";
+        let mut archive = ArchiveReader::new(contents);
+        let mut entries = archive.entries();
+        let entry = entries.next().unwrap().unwrap();
+        assert_eq!(
+            (entry.name(), entry.len(), entry.offset()),
+            ("hello", 3, 32)
+        );
+        assert_eq!(entry.attribute("codec"), Some("zstd"));
+        assert_eq!(entry.original_len(), 10);
+        drop(entry);
+        let entry = entries.next().unwrap().unwrap();
+        assert_eq!(
+            (entry.name(), entry.len(), entry.original_len()),
+            ("plain", 2, 2)
+        );
+        assert!(entry.attributes().is_empty());
+        drop(entry);
+        assert!(entries.next().is_none());
+        assert!(archive.is_extended());
+
+        for (contents, position) in [
+            (&b"DCA2\nfoo\nbad key=1\n1\nx\n"[..], 9),
+            (&b"DCA2\nfoo\nsize=x\n1\nx\n"[..], 9),
+        ] {
+            let mut archive = ArchiveReader::new(contents);
+            let first = archive.entries().next().map(|e| e.map(|_| ()));
+            match first {
+                Some(Err(ArchiveError::CorruptedArchive {
+                    position: p,
+                    section: DecompressionError::Attribute,
+                })) => assert_eq!(p, position),
+                e => panic!("Unexpected result {:?}", e),
+            }
+        }
+
+        // Only the known version is accepted
+        for (contents, position) in [
+            (&b"DCAX\nfoo\n1\nx\n"[..], 3),
+            (&b"DCA2X\nfoo\n1\nx\n"[..], 3),
+        ] {
+            let mut archive = ArchiveReader::new(contents);
+            let first = archive.entries().next().map(|e| e.map(|_| ()));
+            match first {
+                Some(Err(ArchiveError::CorruptedArchive {
+                    position: p,
+                    section: DecompressionError::Header,
+                })) => assert_eq!(p, position),
+                e => panic!("Unexpected result {:?}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Recovery of entries from corrupted or truncated DCA archives
+
+use std::ffi::OsStr;
+use std::io::{self, prelude::*, BufRead, Seek, SeekFrom};
+use std::ops::Range;
+
+use crate::decompress::{on_entry, FileHandler};
+use crate::error::{into_dca_path, ArchiveError, FilePosition, Handler as ErrorHandler, Result};
+use crate::index::{locate_index, INDEX_NAME};
+use crate::reader::is_attribute_key;
+
+use ArchiveError as E;
+
+/// Longest filename line considered plausible
+const MAX_NAME_LEN: u64 = 4096;
+/// Longest file size line considered plausible (digits of [`FilePosition::MAX`])
+const MAX_SIZE_LEN: u64 = 20;
+/// Suffix added to names of entries whose payload is cut off by the end of the archive
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Summary of the salvage operation, see [`salvage_from`]
+#[derive(Debug, Default, PartialEq)]
+pub struct SalvageReport {
+    /// Number of entries passed to the handler, including partial ones
+    pub entries: usize,
+    /// Names (including [`PARTIAL_SUFFIX`]) of entries whose payload was cut off by the end of the archive
+    pub partial: Vec<String>,
+    /// Byte ranges of the archive that couldn't be used. Footer of the final entry missing at the end
+    /// of the archive is reported as empty range at the end
+    pub damaged: Vec<Range<FilePosition>>,
+}
+
+impl SalvageReport {
+    /// Returns true if the whole archive was usable
+    pub fn is_clean(&self) -> bool {
+        self.partial.is_empty() && self.damaged.is_empty()
+    }
+}
+
+/// Header of potential entry
+struct Candidate {
+    name: String,
+    payload: FilePosition,
+    len: FilePosition,
+    attributes: Vec<(String, String)>,
+}
+
+/// Reads line up to `limit` bytes long, excluding the newline. Returns `None` if newline wasn't found.
+fn read_limited_line(reader: &mut impl BufRead, limit: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    reader.take(limit + 1).read_until(b'\n', &mut buf)?;
+    if buf.pop() == Some(b'\n') {
+        Ok(Some(buf))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Tries to interpret data at `position` as filename and file size lines of an entry
+fn parse_candidate(
+    reader: &mut (impl BufRead + Seek),
+    position: FilePosition,
+) -> io::Result<Option<Candidate>> {
+    reader.seek(SeekFrom::Start(position))?;
+
+    let name = match read_limited_line(reader, MAX_NAME_LEN)?
+        .and_then(|name| String::from_utf8(name).ok())
+    {
+        // Names of paths extension are accepted as well
+        Some(name) if into_dca_path(OsStr::new(&name)).is_ok() => name,
+        _ => return Ok(None),
+    };
+    // Attributes of extended format precede the size
+    let mut attributes = Vec::new();
+    let len = loop {
+        let line = match read_limited_line(reader, MAX_NAME_LEN)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if !line.is_empty()
+            && line.len() as u64 <= MAX_SIZE_LEN
+            && line.iter().all(u8::is_ascii_digit)
+        {
+            match std::str::from_utf8(&line).ok().and_then(|s| s.parse().ok()) {
+                Some(len) => break len,
+                None => return Ok(None),
+            }
+        }
+        match String::from_utf8(line)
+            .ok()
+            .as_deref()
+            .and_then(|s| s.split_once('='))
+        {
+            Some((key, value)) if is_attribute_key(key) => {
+                attributes.push((key.to_owned(), value.to_owned()))
+            }
+            _ => return Ok(None),
+        }
+    };
+    let payload = reader.stream_position()?;
+    Ok(Some(Candidate {
+        name,
+        payload,
+        len,
+        attributes,
+    }))
+}
+
+/// Returns true if byte at `position` is an entry footer
+fn footer_at(reader: &mut (impl BufRead + Seek), position: FilePosition) -> io::Result<bool> {
+    reader.seek(SeekFrom::Start(position))?;
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(&buf == b"\n")
+}
+
+/// Finds the start of the line following `position`, or `end` if there's none
+fn next_line(
+    reader: &mut (impl BufRead + Seek),
+    position: FilePosition,
+    end: FilePosition,
+) -> io::Result<FilePosition> {
+    reader.seek(SeekFrom::Start(position))?;
+    let skipped = reader.skip_until(b'\n')? as FilePosition;
+    Ok(std::cmp::min(position + skipped, end))
+}
+
+/// Passes entry to the handler
+fn process_entry(
+    reader: &mut (impl BufRead + Seek),
+    candidate: Candidate,
+    handle_file: &mut impl FileHandler,
+    handle_err: &impl ErrorHandler,
+) -> Result<()> {
+    let Candidate {
+        name,
+        payload,
+        len,
+        attributes,
+    } = candidate;
+    reader
+        .seek(SeekFrom::Start(payload))
+        .map_err(E::ArchiveIo)?;
+    match on_entry(handle_file, &name, len, &attributes, &mut reader.take(len)) {
+        Ok(()) => Ok(()),
+        Err(
+            e @ E::BadFileIo(..)
+            | e @ E::UnsafeEntryName(..)
+            | e @ E::FileExists(..)
+            | e @ E::FileSkipped(..)
+            | e @ E::ChecksumMismatch(..),
+        ) => handle_err.on_err(e),
+        Err(e) => Err(e),
+    }
+}
+
+/// Extracts as much as possible from damaged archive.
+///
+/// Works like [`crate::decompress::decompress_from_seekable`] for valid archives. When structure of the archive
+/// is broken, the damaged part is skipped by scanning forward for the next position that looks like an entry
+/// (plausible name and size lines, followed by a footer after the payload), and processing continues from there.
+///
+/// Entry whose payload is cut off by the end of the archive is passed to the handler with all the remaining
+/// data, and its name gets [`PARTIAL_SUFFIX`] appended. Final entry missing just its footer is passed as is,
+/// but still reported as damage.
+///
+/// Note that the scan is heuristic - content of damaged entries may be mistaken for entries.
+/// Positions in the report are absolute positions in the reader.
+pub fn salvage_from(
+    reader: &mut (impl BufRead + Seek),
+    handle_file: &mut impl FileHandler,
+    handle_err: &impl ErrorHandler,
+) -> Result<SalvageReport> {
+    let mut report = SalvageReport::default();
+
+    let start = reader.stream_position().map_err(E::ArchiveIo)?;
+    // Index is only skipped if it's intact, damaged one is just another entry
+    let index = locate_index(reader)
+        .unwrap_or(None)
+        .map(|offset| start + offset);
+    let end = reader.seek(SeekFrom::End(0)).map_err(E::ArchiveIo)?;
+    reader.seek(SeekFrom::Start(start)).map_err(E::ArchiveIo)?;
+
+    // Header
+    let mut damaged_from = None;
+    let mut header = [0u8; 4];
+    let mut position = if reader.read_exact(&mut header).is_ok() && &header == b"DCA\n" {
+        start + 4
+    } else if &header == b"DCA2" && footer_at(reader, start + 4).map_err(E::ArchiveIo)? {
+        start + 5
+    } else {
+        damaged_from = Some(start);
+        next_line(reader, start, end).map_err(E::ArchiveIo)?
+    };
+
+    // Entry cut off by the end of the archive is accepted only if no complete entry follows,
+    // until then it's kept aside
+    let mut pending_partial: Option<(FilePosition, Candidate)> = None;
+    while position < end {
+        let candidate = parse_candidate(reader, position).map_err(E::ArchiveIo)?;
+        let candidate = match candidate {
+            Some(c) if c.payload.saturating_add(c.len) > end => {
+                if pending_partial.is_none() {
+                    pending_partial = Some((position, c));
+                }
+                None
+            }
+            Some(c) if c.payload + c.len == end => {
+                // Only footer is missing, so the entry is complete
+                report.damaged.push(end..end);
+                Some(c)
+            }
+            Some(c) if footer_at(reader, c.payload + c.len).map_err(E::ArchiveIo)? => Some(c),
+            _ => {
+                if pending_partial.is_none() {
+                    damaged_from.get_or_insert(position);
+                }
+                None
+            }
+        };
+        let candidate = match candidate {
+            Some(c) => c,
+            None => {
+                position = next_line(reader, position, end).map_err(E::ArchiveIo)?;
+                continue;
+            }
+        };
+        if let Some((partial_position, _)) = pending_partial.take() {
+            damaged_from.get_or_insert(partial_position);
+        }
+        if let Some(from) = damaged_from.take() {
+            report.damaged.push(from..position);
+        }
+
+        let footer = candidate.payload + candidate.len;
+        if Some(position) != index || candidate.name != INDEX_NAME {
+            process_entry(reader, candidate, handle_file, handle_err)?;
+            report.entries += 1;
+        }
+        position = footer + 1;
+    }
+    if let Some((partial_position, candidate)) = pending_partial {
+        if let Some(from) = damaged_from.take() {
+            report.damaged.push(from..partial_position);
+        }
+        let name = format!("{}{}", candidate.name, PARTIAL_SUFFIX);
+        report.partial.push(name.clone());
+        let candidate = Candidate {
+            name,
+            len: end - candidate.payload,
+            ..candidate
+        };
+        process_entry(reader, candidate, handle_file, handle_err)?;
+        report.entries += 1;
+    } else if let Some(from) = damaged_from {
+        report.damaged.push(from..end);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use crate::decompress::{CallbackFileHandler, DefaultErrorHandler};
+
+    fn salvage(contents: &[u8]) -> (Vec<(String, Vec<u8>)>, SalvageReport) {
+        let mut found = Vec::new();
+        let mut fhandler = CallbackFileHandler(|name, _len, reader| {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).map_err(E::ArchiveIo)?;
+            found.push((name.to_owned(), buf));
+            Ok(())
+        });
+        let report = salvage_from(
+            &mut Cursor::new(contents),
+            &mut fhandler,
+            &DefaultErrorHandler::new(Path::new("broken.dca")),
+        )
+        .unwrap();
+        (found, report)
+    }
+
+    fn entries(list: &[(&str, &[u8])]) -> Vec<(String, Vec<u8>)> {
+        list.iter()
+            .map(|(name, data)| (name.to_string(), data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let (found, report) = salvage(b"DCA\nfoo\n3\n1\n3\nbar\n0\n\n");
+        assert_eq!(found, entries(&[("foo", b"1\n3"), ("bar", b"")]));
+        assert!(report.is_clean());
+        assert_eq!(report.entries, 2);
+    }
+
+    #[test]
+    fn test_damaged() {
+        // Broken size of the first entry, garbage between entries, truncated final entry
+        let contents = b"DCA\nfoo\nx3\n123\nbar\n2\nab\ngarbage\nbaz\n10\n12345";
+        let (found, report) = salvage(contents);
+        assert_eq!(found, entries(&[("bar", b"ab"), ("baz.partial", b"12345")]));
+        assert_eq!(report.partial, vec!["baz.partial".to_owned()]);
+        assert_eq!(report.damaged, vec![4..15, 24..32]);
+    }
+
+    #[test]
+    fn test_broken_header() {
+        let (found, report) = salvage(b"DC\nfoo\n1\nx\n");
+        assert_eq!(found, entries(&[("foo", b"x")]));
+        assert_eq!(report.damaged, vec![0..3]);
+    }
+
+    #[test]
+    fn test_missing_footer() {
+        let (found, report) = salvage(b"DCA\nfoo\n1\nx\nbar\n2\nab");
+        assert_eq!(found, entries(&[("foo", b"x"), ("bar", b"ab")]));
+        assert!(report.partial.is_empty());
+        assert_eq!(report.damaged, vec![20..20]);
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_extended() {
+        let (found, report) = salvage(b"DCA2\nfoo\nkey=value\n1\nx\nbar\n0\n\n");
+        assert_eq!(found, entries(&[("foo", b"x"), ("bar", b"")]));
+        assert!(report.is_clean());
+    }
+}
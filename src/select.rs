@@ -0,0 +1,177 @@
+//! Selection of archive entries by their names
+
+/// Matches `name` against shell-like wildcard `pattern`
+///
+/// Supported syntax:
+///
+/// - `*` matches any sequence of characters, including empty one
+/// - `?` matches any single character
+/// - `[abc]`, `[a-z]` match single character from the set or range, `[!abc]` its complement
+///
+/// Any other character, including unpaired `[`, matches itself.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and position in name it's currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_class(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                // Not a class, literal bracket
+                None if name[n] == '[' => Some(1),
+                None => None,
+            },
+            Some(&ch) if ch == name[n] => Some(1),
+            _ => None,
+        };
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                // Let the last star consume one more character
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p;
+                n = star_n + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+/// Matches character class starting at the beginning of `pattern` (at `[`)
+///
+/// Returns whether the class matches and length of the class in the pattern, or `None` if the class is not terminated.
+fn match_class(pattern: &[char], ch: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match pattern.get(i)? {
+            // Closing bracket as the first member is taken literally
+            ']' if !first => break,
+            &low => {
+                if pattern.get(i + 1) == Some(&'-')
+                    && !matches!(pattern.get(i + 2), None | Some(']'))
+                {
+                    let high = pattern[i + 2];
+                    matched |= low <= ch && ch <= high;
+                    i += 3;
+                } else {
+                    matched |= low == ch;
+                    i += 1;
+                }
+            }
+        }
+        first = false;
+    }
+    Some((matched != negated, i + 1))
+}
+
+/// Single criterion for selecting entries, see [`Selection`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// Selects entries with exactly this name
+    Name(String),
+    /// Selects entries matching wildcard pattern, see [`glob_matches`]
+    Glob(String),
+}
+
+impl Selector {
+    /// Returns true if entry of given name is selected
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::Name(selected) => selected == name,
+            Selector::Glob(pattern) => glob_matches(pattern, name),
+        }
+    }
+}
+
+/// Set of [`Selector`]s. Entry is selected if it matches any of them.
+///
+/// Empty selection selects all entries.
+///
+/// # Example
+///
+/// ```
+/// use dca::select::Selection;
+///
+/// let selection = Selection::new().name("notes.txt").glob("report-*.csv");
+/// assert!(selection.matches("report-2021.csv"));
+/// assert!(!selection.matches("notes.md"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    selectors: Vec<Selector>,
+}
+
+impl Selection {
+    /// Creates empty selection
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds selector by exact name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.selectors.push(Selector::Name(name.into()));
+        self
+    }
+    /// Adds selector by wildcard pattern
+    pub fn glob(mut self, pattern: impl Into<String>) -> Self {
+        self.selectors.push(Selector::Glob(pattern.into()));
+        self
+    }
+    /// Returns true if entry of given name is selected
+    pub fn matches(&self, name: &str) -> bool {
+        self.selectors.is_empty() || self.selectors.iter().any(|s| s.matches(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_matches("report-*.csv", "report-2021.csv"));
+        assert!(glob_matches("report-*.csv", "report-.csv"));
+        assert!(!glob_matches("report-*.csv", "report-2021.csv.bak"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*a*b", "xxaxxab"));
+        assert!(!glob_matches("*a*b", "xxaxxa"));
+        assert!(glob_matches("file?.txt", "file1.txt"));
+        assert!(!glob_matches("file?.txt", "file.txt"));
+        assert!(glob_matches("file[0-9].txt", "file7.txt"));
+        assert!(!glob_matches("file[!0-9].txt", "file7.txt"));
+        assert!(glob_matches("[]]", "]"));
+        assert!(glob_matches("[*]", "*"));
+        assert!(!glob_matches("[*]", "a"));
+        assert!(glob_matches("[abc", "[abc"));
+        assert!(glob_matches("žluť*", "žluťoučký"));
+    }
+
+    #[test]
+    fn test_selection() {
+        assert!(Selection::new().matches("anything"));
+
+        let selection = Selection::new().name("a*").glob("b*");
+        assert!(selection.matches("a*"));
+        assert!(!selection.matches("ab"));
+        assert!(selection.matches("bc"));
+    }
+}
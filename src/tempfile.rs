@@ -0,0 +1,171 @@
+//! Temporary files placed next to their final destination, allowing atomic replacement
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::error;
+
+/// Temporary file that gets deleted unless it's persisted under its final name
+pub(crate) struct TempFile {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates new empty file in the same directory as `target`, so it can be renamed into its place
+    pub(crate) fn new_sibling(target: &Path) -> io::Result<Self> {
+        Self::create(target, 0o666)
+    }
+
+    /// Variant of [`Self::new_sibling`] for data not meant to be shared, accessible only by its owner on Unix
+    pub(crate) fn new_private(target: &Path) -> io::Result<Self> {
+        Self::create(target, 0o600)
+    }
+
+    /// Creates the file with permission bits `mode`, subject to umask (used only on Unix)
+    fn create(target: &Path, mode: u32) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let base = target.file_name().unwrap_or_default();
+        loop {
+            let mut name = OsString::from(".");
+            name.push(base);
+            name.push(format!(
+                ".{}-{}.tmp",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let path = dir.join(name);
+            let mut options = OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+            #[cfg(not(unix))]
+            let _ = mode;
+            match options.open(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        file: Some(file),
+                        path,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("temporary file is open until persisted")
+    }
+
+    /// Atomically moves the file to `target`, replacing it if exists
+    ///
+    /// On failure, temporary file is deleted.
+    pub(crate) fn persist(mut self, target: &Path) -> io::Result<()> {
+        // Close the file before renaming, some platforms don't allow renaming opened files
+        drop(self.file.take());
+        fs::rename(&self.path, target)?;
+        self.path = PathBuf::new();
+        Ok(())
+    }
+
+    /// Moves the file to `target` only if nothing exists there, failing with [`io::ErrorKind::AlreadyExists`]
+    /// otherwise. On that failure, the temporary file is kept, so that another target can be tried.
+    ///
+    /// File created at `target` concurrently is never replaced, as the file is hard-linked to `target` first
+    /// and its temporary name removed afterwards. Filesystems without hard links fall back to checking
+    /// whether `target` exists before renaming.
+    pub(crate) fn persist_new(&mut self, target: &Path) -> io::Result<()> {
+        match fs::hard_link(&self.path, target) {
+            Ok(()) => {
+                drop(self.file.take());
+                let path = std::mem::take(&mut self.path);
+                if let Err(e) = fs::remove_file(&path) {
+                    error!(
+                        "Temporary file {:?} couldn't be deleted due to error {}. Please remove it manually.",
+                        path, e
+                    );
+                }
+                Ok(())
+            }
+            Err(e)
+                if e.kind() == io::ErrorKind::Unsupported
+                    || e.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                if fs::symlink_metadata(target).is_ok() {
+                    return Err(io::ErrorKind::AlreadyExists.into());
+                }
+                drop(self.file.take());
+                fs::rename(&self.path, target)?;
+                self.path = PathBuf::new();
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.path.as_os_str().is_empty() {
+            return;
+        }
+        drop(self.file.take());
+        if let Err(e) = fs::remove_file(&self.path) {
+            error!(
+                "Temporary file {:?} couldn't be deleted due to error {}. Please remove it manually.",
+                self.path, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::prelude::*;
+
+    use assert_fs::prelude::*;
+
+    use crate::testutils::*;
+
+    #[test]
+    fn test_persist_new() {
+        let dir = make_dir();
+        dir.child("existing").write_str("old").unwrap();
+
+        let target = dir.child("existing");
+        let mut temp = TempFile::new_sibling(target.path()).unwrap();
+        temp.file().write_all(b"new").unwrap();
+        let err = temp.persist_new(target.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        target.assert("old");
+
+        temp.persist_new(dir.child("fresh").path()).unwrap();
+        dir.child("fresh").assert("new");
+        assert_eq!(dir_size(&dir), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = make_dir();
+        let mut temp = TempFile::new_private(dir.child("spool").path()).unwrap();
+        let mode = temp.file().metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
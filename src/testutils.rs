@@ -7,8 +7,8 @@ use std::iter::ExactSizeIterator;
 use std::path::Path;
 
 pub fn assert_eq_iters<Item1, Item2>(
-    it1: impl Iterator<Item = Item1> + ExactSizeIterator,
-    it2: impl Iterator<Item = Item2> + ExactSizeIterator,
+    it1: impl ExactSizeIterator<Item = Item1>,
+    it2: impl ExactSizeIterator<Item = Item2>,
 ) where
     Item1: PartialEq<Item2>,
     Item1: Debug,
@@ -21,7 +21,7 @@ pub fn assert_eq_iters<Item1, Item2>(
 }
 
 pub fn dir_size(dir: &impl AsRef<Path>) -> usize {
-    read_dir(dir.as_ref()).unwrap().into_iter().count()
+    read_dir(dir.as_ref()).unwrap().count()
 }
 
 pub fn make_dir() -> TempDir {
@@ -0,0 +1,273 @@
+//! Collection of files to compress from directory trees
+//!
+//! As DCA format is flat, files found in subdirectories have to be given flat entry names, see [`Flatten`].
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{
+    into_dca_filename, into_dca_path, warn, ArchiveError, DcaFilenameError, Result,
+};
+use crate::select::{glob_matches, Selection};
+
+use ArchiveError as E;
+
+/// Strategy of naming entries for files found in directories
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Flatten {
+    /// Only filename is used, for example `file`
+    #[default]
+    Basename,
+    /// Components of the path are joined by given separator, for example `dir__sub__file`
+    ///
+    /// Path starts with the name of the directory given as root, or is relative to it if it has no name (such as `.`).
+    Join(String),
+    /// Path is kept with `/` separators, for example `dir/sub/file`.
+    /// Archive needs to be created with paths extension, see [`crate::error::into_dca_path`]
+    Paths,
+}
+
+/// Recursive collector of files, producing list of paths along with entry names
+///
+/// Include and exclude patterns (see [`glob_matches`]) are matched against the path relative to the root,
+/// with components separated by `/`, such as `dir/sub/file`. Excluded directories are not entered at all.
+/// File is collected if it matches any include pattern (or there are none) and doesn't match any exclude pattern.
+///
+/// Symbolic links to directories are not followed. Anything but regular files and symbolic links to them
+/// (such as FIFOs or sockets) is skipped with a warning.
+///
+/// # Example
+///
+/// ```no_run
+/// use dca::compress::{compress_files_with, NamedFileHandler};
+/// use dca::walk::{Flatten, Walk};
+///
+/// let files = Walk::new()
+///     .flatten(Flatten::Join("__".to_owned()))
+///     .include("*.rs")
+///     .exclude("*/target")
+///     .collect(&["project"])
+///     .expect("failed to collect files");
+/// compress_files_with("sources.dca", &mut NamedFileHandler::new(files), false)
+///     .expect("failed to create the archive");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Walk {
+    flatten: Flatten,
+    include: Selection,
+    exclude: Vec<String>,
+}
+
+impl Walk {
+    /// Creates collector using [`Flatten::Basename`] and no filters
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets naming strategy of collected files
+    pub fn flatten(mut self, flatten: Flatten) -> Self {
+        self.flatten = flatten;
+        self
+    }
+    /// Adds pattern of files to collect
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include = self.include.glob(pattern);
+        self
+    }
+    /// Adds pattern of files and directories to leave out
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Collects files from given roots, which can be both files and directories
+    ///
+    /// Files given directly are named by their filename and aren't subject to filters.
+    /// Names are validated with [`into_dca_filename`]. Fails with [`ArchiveError::NameCollision`]
+    /// if two files would be stored under the same name.
+    pub fn collect<P: AsRef<Path>>(
+        &self,
+        roots: impl IntoIterator<Item = P>,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let mut files = Vec::new();
+        for root in roots {
+            let root = root.as_ref();
+            let meta = fs::metadata(root).map_err(|e| E::BadFileIo(root.to_owned(), e))?;
+            if !meta.is_dir() {
+                let name = crate::writer::entry_name(root)?;
+                files.push((root.to_owned(), name.to_owned()));
+                continue;
+            }
+            let mut prefix = Vec::new();
+            if let Some(Component::Normal(name)) = root.components().next_back() {
+                prefix.push(utf8_component(root, name)?);
+            }
+            self.walk_dir(root, &mut prefix, &mut files)?;
+        }
+
+        let mut names: HashMap<&str, &Path> = HashMap::new();
+        for (path, name) in &files {
+            if let Some(first) = names.insert(name, path) {
+                return Err(E::NameCollision(
+                    name.clone(),
+                    first.to_owned(),
+                    path.clone(),
+                ));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Collects files of directory `dir`, whose path relative to the root is `prefix`
+    fn walk_dir(
+        &self,
+        dir: &Path,
+        prefix: &mut Vec<String>,
+        files: &mut Vec<(PathBuf, String)>,
+    ) -> Result<()> {
+        let bad_io = |e| E::BadFileIo(dir.to_owned(), e);
+        let mut entries = fs::read_dir(dir)
+            .map_err(bad_io)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(bad_io)?;
+        entries.sort();
+
+        for path in entries {
+            let name = path.file_name().expect("directory entries have names");
+            prefix.push(utf8_component(&path, name)?);
+            let relative = prefix.join("/");
+            if self.exclude.iter().any(|p| glob_matches(p, &relative)) {
+                prefix.pop();
+                continue;
+            }
+
+            let file_type = fs::symlink_metadata(&path)
+                .map_err(|e| E::BadFileIo(path.clone(), e))?
+                .file_type();
+            let is_file = file_type.is_file()
+                || file_type.is_symlink() && fs::metadata(&path).is_ok_and(|meta| meta.is_file());
+            if file_type.is_dir() {
+                self.walk_dir(&path, prefix, files)?;
+            } else if !is_file {
+                warn!("{:?} is not a regular file, skipping it", path);
+            } else if self.include.matches(&relative) {
+                let entry_name = match &self.flatten {
+                    Flatten::Basename => prefix.last().cloned().unwrap_or_default(),
+                    Flatten::Join(separator) => prefix.join(separator),
+                    Flatten::Paths => prefix.join("/"),
+                };
+                let validate = if self.flatten == Flatten::Paths {
+                    into_dca_path
+                } else {
+                    into_dca_filename
+                };
+                validate(OsStr::new(&entry_name))
+                    .map_err(|e| E::InvalidDcaFilename(path.clone(), e))?;
+                files.push((path, entry_name));
+            }
+            prefix.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Converts path component into string, as entry names need to be valid UTF-8
+fn utf8_component(path: &Path, component: &OsStr) -> Result<String> {
+    component
+        .to_str()
+        .map(str::to_owned)
+        .ok_or_else(|| E::InvalidDcaFilename(path.to_owned(), DcaFilenameError::NotUnicode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_fs::prelude::*;
+
+    use crate::testutils::*;
+
+    fn names(files: Vec<(PathBuf, String)>) -> Vec<String> {
+        files.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = make_dir();
+        dir.child("tree/a.rs").write_str("a").unwrap();
+        dir.child("tree/sub/b.rs").write_str("b").unwrap();
+        dir.child("tree/sub/c.txt").write_str("c").unwrap();
+        dir.child("tree/target/d.rs").write_str("d").unwrap();
+        dir.child("single").write_str("e").unwrap();
+
+        let files = Walk::new()
+            .collect(&[dir.child("tree"), dir.child("single")])
+            .unwrap();
+        assert_eq!(names(files), ["a.rs", "b.rs", "c.txt", "d.rs", "single"]);
+
+        let files = Walk::new()
+            .flatten(Flatten::Join("__".to_owned()))
+            .include("*.rs")
+            .exclude("*/target")
+            .collect(&[dir.child("tree")])
+            .unwrap();
+        assert_eq!(
+            files,
+            [
+                (
+                    dir.child("tree/a.rs").to_path_buf(),
+                    "tree__a.rs".to_owned()
+                ),
+                (
+                    dir.child("tree/sub/b.rs").to_path_buf(),
+                    "tree__sub__b.rs".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collision() {
+        let dir = make_dir();
+        dir.child("tree/file").write_str("a").unwrap();
+        dir.child("tree/sub/file").write_str("b").unwrap();
+
+        match Walk::new().collect(&[dir.child("tree")]).unwrap_err() {
+            ArchiveError::NameCollision(name, first, second) => {
+                assert_eq!(name, "file");
+                assert!(first.ends_with("tree/file"));
+                assert!(second.ends_with("tree/sub/file"));
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+        let files = Walk::new()
+            .flatten(Flatten::Join("__".to_owned()))
+            .collect(&[dir.child("tree")])
+            .unwrap();
+        assert_eq!(names(files), ["tree__file", "tree__sub__file"]);
+
+        let files = Walk::new()
+            .flatten(Flatten::Paths)
+            .collect(&[dir.child("tree")])
+            .unwrap();
+        assert_eq!(names(files), ["tree/file", "tree/sub/file"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_special_files() {
+        use std::os::unix::{fs::symlink, net::UnixListener};
+
+        let dir = make_dir();
+        dir.child("tree/file").write_str("a").unwrap();
+        dir.child("other/inner").write_str("b").unwrap();
+        symlink(dir.child("tree/file"), dir.child("tree/file-link")).unwrap();
+        symlink(dir.child("other"), dir.child("tree/dir-link")).unwrap();
+        let _socket = UnixListener::bind(dir.child("tree/socket")).unwrap();
+
+        let files = Walk::new().collect(&[dir.child("tree")]).unwrap();
+        assert_eq!(names(files), ["file", "file-link"]);
+    }
+}
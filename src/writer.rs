@@ -0,0 +1,247 @@
+//! Incremental construction of DCA archives
+
+use std::ffi::OsStr;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+use crate::compress::open_file;
+use crate::error::{into_dca_filename, ArchiveError, FilePosition, Result};
+
+use ArchiveError as E;
+
+/// Returns name under which file at `path` is stored in the archive by default, which is its filename
+///
+/// Fails with [`ArchiveError::InvalidDcaFilename`] if the filename can't be stored (see [`into_dca_filename`]),
+/// or with [`ArchiveError::BadFileIo`] of kind [`io::ErrorKind::NotFound`] if `path` has no filename, such as `..`.
+///
+/// # Example
+///
+/// ```
+/// use dca::writer::entry_name;
+/// use std::path::Path;
+///
+/// assert_eq!(entry_name(Path::new("logs/app.log")).unwrap(), "app.log");
+/// assert!(entry_name(Path::new("logs/..")).is_err());
+/// ```
+pub fn entry_name(path: &Path) -> Result<&str> {
+    let fname = path
+        .file_name()
+        .ok_or_else(|| E::BadFileIo(path.to_owned(), io::ErrorKind::NotFound.into()))?;
+
+    into_dca_filename(fname).map_err(|e| E::InvalidDcaFilename(path.to_owned(), e))
+}
+
+/// Writes single complete entry into the archive
+///
+/// `reader` shall provide exactly `len` bytes. If it provides less or more, [`ArchiveError::BadFileIo`]
+/// mentioning `source` is returned and the archive is left in inconsistent state.
+/// `attributes` may be given only in archives of extended format.
+pub(crate) fn write_entry(
+    writer: &mut impl Write,
+    name: &str,
+    attributes: &[(String, String)],
+    len: FilePosition,
+    reader: &mut impl BufRead,
+    source: &Path,
+) -> Result<()> {
+    write_entry_header(writer, name, attributes, len).map_err(E::ArchiveIo)?;
+
+    let mut remaining = len;
+    loop {
+        let buf = reader
+            .fill_buf()
+            .map_err(|e| E::BadFileIo(source.to_owned(), e))?;
+        if buf.is_empty() {
+            if remaining != 0 {
+                return Err(E::BadFileIo(
+                    source.to_owned(),
+                    io::ErrorKind::UnexpectedEof.into(),
+                ));
+            }
+            break;
+        }
+        if remaining == 0 {
+            return Err(E::BadFileIo(
+                source.to_owned(),
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("source provides more than declared {} bytes", len),
+                ),
+            ));
+        }
+        let new_len = std::cmp::min(remaining, buf.len() as FilePosition) as usize;
+        writer.write_all(&buf[..new_len]).map_err(E::ArchiveIo)?;
+        remaining -= new_len as FilePosition;
+        reader.consume(new_len);
+    }
+    writer.write_all(b"\n").map_err(E::ArchiveIo)?;
+    Ok(())
+}
+
+/// Writes filename, attribute and file size lines of an entry
+pub(crate) fn write_entry_header(
+    writer: &mut impl Write,
+    name: &str,
+    attributes: &[(String, String)],
+    len: FilePosition,
+) -> io::Result<()> {
+    writeln!(writer, "{}", name)?;
+    for (key, value) in attributes {
+        writeln!(writer, "{}={}", key, value)?;
+    }
+    writeln!(writer, "{}", len)
+}
+
+/// Builder of DCA archives, allowing entries to be added one by one from various sources
+///
+/// Entry names are validated the same way as in [`crate::compress::compress_into`].
+/// If entry's source fails to provide exactly the declared amount of data, the archive can't be completed
+/// and all further operations fail.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use dca::writer::ArchiveWriter;
+///
+/// # fn main() -> dca::error::Result<()> {
+/// let file = File::create("archive.dca").map_err(dca::error::ArchiveError::ArchiveIo)?;
+/// let mut archive = ArchiveWriter::new(file)?;
+/// archive.append_path("notes.txt")?;
+/// archive.append_bytes("hello.txt", b"Hello world!")?;
+/// archive.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    /// Set when entry was left incomplete
+    poisoned: bool,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Starts new archive, writing its header into `writer`
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"DCA\n").map_err(E::ArchiveIo)?;
+        Ok(Self {
+            writer,
+            poisoned: false,
+        })
+    }
+
+    fn check_poisoned(&self) -> Result<()> {
+        if self.poisoned {
+            Err(E::ArchiveIo(io::Error::other(
+                "archive was left inconsistent by previous failure",
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn append<R: BufRead>(
+        &mut self,
+        name: &str,
+        len: FilePosition,
+        reader: &mut R,
+        source: &Path,
+    ) -> Result<()> {
+        self.check_poisoned()?;
+        let res = write_entry(&mut self.writer, name, &[], len, reader, source);
+        if res.is_err() {
+            self.poisoned = true;
+        }
+        res
+    }
+
+    /// Adds file from the filesystem. Only its filename is preserved in the archive
+    ///
+    /// Inputs of unknown size, such as pipes, are read whole first, see [`crate::compress::DefaultFileHandler`].
+    pub fn append_path(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.check_poisoned()?;
+        let path = path.as_ref();
+        let name = entry_name(path)?;
+        let (mut reader, len, _) = open_file(path)?;
+
+        self.append(name, len, &mut reader, path)
+    }
+
+    /// Adds entry with in-memory contents
+    pub fn append_bytes(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.append_reader(name, data.len() as FilePosition, data)
+    }
+
+    /// Adds entry with contents provided by `reader`, which shall provide exactly `len` bytes
+    pub fn append_reader(
+        &mut self,
+        name: &str,
+        len: FilePosition,
+        reader: impl Read,
+    ) -> Result<()> {
+        self.check_poisoned()?;
+        let source = PathBuf::from(name);
+        let name = into_dca_filename(OsStr::new(name))
+            .map_err(|e| E::InvalidDcaFilename(source.clone(), e))?;
+
+        self.append(name, len, &mut io::BufReader::new(reader), &source)
+    }
+
+    /// Completes the archive, flushing and returning underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.check_poisoned()?;
+        self.writer.flush().map_err(E::ArchiveIo)?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_fs::prelude::*;
+
+    use crate::error::DcaFilenameError;
+    use crate::testutils::*;
+
+    #[test]
+    fn test_append() {
+        let dir = make_dir();
+        dir.child("file.txt").write_str("data").unwrap();
+
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        archive.append_path(dir.child("file.txt")).unwrap();
+        archive.append_bytes("bytes", b"\x00\n").unwrap();
+        archive.append_reader("reader", 3, &b"abc"[..]).unwrap();
+        archive.append_bytes("empty", b"").unwrap();
+        let out = archive.finish().unwrap();
+
+        assert_eq!(
+            out,
+            b"DCA\nfile.txt\n4\ndata\nbytes\n2\n\x00\n\nreader\n3\nabc\nempty\n0\n\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        match archive.append_bytes("foo/bar", b"").unwrap_err() {
+            ArchiveError::InvalidDcaFilename(_, DcaFilenameError::InvalidChar('/', 3)) => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        // Shorter source breaks the archive
+        match archive.append_reader("short", 5, &b"abc"[..]).unwrap_err() {
+            ArchiveError::BadFileIo(_, e) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        assert!(archive.append_bytes("ok", b"").is_err());
+        assert!(archive.finish().is_err());
+
+        // So does longer one
+        let mut archive = ArchiveWriter::new(Vec::new()).unwrap();
+        match archive.append_reader("long", 2, &b"abc"[..]).unwrap_err() {
+            ArchiveError::BadFileIo(_, e) if e.kind() == io::ErrorKind::InvalidData => (),
+            e => panic!("Unexpected error {:?}", e),
+        }
+        assert!(archive.finish().is_err());
+    }
+}
@@ -25,7 +25,7 @@ fn cli() {
 
         Command::cargo_bin("dca")
             .unwrap()
-            .args(&[
+            .args([
                 "--compress",
                 "notes.txt",
                 "dump.bin",
@@ -38,6 +38,49 @@ fn cli() {
             .success();
 
         dir.child("archive.dca").assert(contents);
+
+        // Automatically named archive isn't replaced without --force
+        dir.child("notes.txt.dca").write_str("old").unwrap();
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["notes.txt"])
+            .current_dir(dir.path())
+            .assert()
+            .failure()
+            .stderr(predicates::function::function(|err: &str| {
+                err.matches("already exists").count() == 1
+            }));
+        dir.child("notes.txt.dca").assert("old");
+
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["notes.txt", "--force"])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+        dir.child("notes.txt.dca")
+            .assert(b"DCA\nnotes.txt\n8\nmy\nnotes\n" as &[u8]);
+
+        // Existing files containing '=' are taken whole, otherwise name follows the last '='
+        dir.child("k=v.txt").write_str("kv").unwrap();
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["-c", "k=v.txt", "empty=renamed", "-o", "names.dca"])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+        dir.child("names.dca")
+            .assert(b"DCA\nk=v.txt\n2\nkv\nrenamed\n0\n\n" as &[u8]);
+
+        // Renames follow the same convention
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["--rename", "names.dca", "k=v.txt=kv.txt"])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+        dir.child("names.dca")
+            .assert(b"DCA\nkv.txt\n2\nkv\nrenamed\n0\n\n" as &[u8]);
     }
     {
         let dir = TempDir::new().unwrap();
@@ -46,7 +89,7 @@ fn cli() {
 
         Command::cargo_bin("dca")
             .unwrap()
-            .args(&["--decompress", "archive.dca", "--output", "."])
+            .args(["--decompress", "archive.dca", "--output", "."])
             .current_dir(dir.path())
             .assert()
             .success();
@@ -55,4 +98,119 @@ fn cli() {
         dir.child("dump.bin").assert(b"\x12\x34\x56\0\0" as &[u8]);
         dir.child("empty").assert("");
     }
+    {
+        let dir = TempDir::new().unwrap();
+
+        dir.child("notes.txt").write_str("my\nnotes").unwrap();
+        dir.child("dump.bin")
+            .write_binary(b"\x12\x34\x56\0\0")
+            .unwrap();
+        dir.child("empty").touch().unwrap();
+
+        // Archive can be written to standard output and read from standard input
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["-c", "notes.txt", "dump.bin", "empty", "-o", "-"])
+            .current_dir(dir.path())
+            .assert()
+            .success()
+            .stdout(contents);
+
+        dir.child("out").create_dir_all().unwrap();
+        assert_cmd::Command::cargo_bin("dca")
+            .unwrap()
+            .args(["-d", "-", "-o", "out"])
+            .current_dir(dir.path())
+            .write_stdin(contents)
+            .assert()
+            .success();
+        dir.child("out/notes.txt").assert("my\nnotes");
+        dir.child("out/empty").assert("");
+
+        // List read from standard input can't name it as a file
+        assert_cmd::Command::cargo_bin("dca")
+            .unwrap()
+            .args(["-c", "-T", "-", "-o", "list.dca"])
+            .current_dir(dir.path())
+            .write_stdin("notes.txt\n-\n")
+            .timeout(std::time::Duration::from_secs(10))
+            .assert()
+            .failure();
+        dir.child("list.dca").assert(predicates::path::missing());
+    }
+    {
+        let dir = TempDir::new().unwrap();
+
+        dir.child("archive.dca")
+            .write_binary(b"DCA\nnotes.txt\n8\nmy\nnotes\n")
+            .unwrap();
+        dir.child("dump.bin")
+            .write_binary(b"\x12\x34\x56\0\0")
+            .unwrap();
+        dir.child("empty").touch().unwrap();
+
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["--append", "archive.dca", "dump.bin", "empty"])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+
+        dir.child("archive.dca").assert(contents);
+
+        // Options of added files are honored, entries with checksums need archive in extended format
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["--append", "archive.dca", "empty", "--checksum", "crc32"])
+            .current_dir(dir.path())
+            .assert()
+            .failure();
+        dir.child("archive.dca").assert(contents);
+
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args([
+                "--append",
+                "archive.dca",
+                "empty=copy",
+                "--duplicates",
+                "reject",
+            ])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args(["--append", "archive.dca", "empty", "--duplicates", "reject"])
+            .current_dir(dir.path())
+            .assert()
+            .failure();
+        assert_eq!(
+            std::fs::read(dir.child("archive.dca").path()).unwrap(),
+            [contents, b"copy\n0\n\n"].concat()
+        );
+    }
+    {
+        let dir = TempDir::new().unwrap();
+
+        dir.child("archive.dca")
+            .write_binary(b"DCA\nreport[1].txt\n1\na\nreport1.txt\n1\nb\n")
+            .unwrap();
+
+        // Wildcard characters in exact names aren't special
+        Command::cargo_bin("dca")
+            .unwrap()
+            .args([
+                "--decompress",
+                "archive.dca",
+                "--only-name",
+                "report[1].txt",
+            ])
+            .current_dir(dir.path())
+            .assert()
+            .success();
+
+        dir.child("report[1].txt").assert("a");
+        dir.child("report1.txt").assert(predicates::path::missing());
+    }
 }